@@ -0,0 +1,110 @@
+//! Compares `join_all` against a join that repolls every pending branch on each wake.
+//!
+//! Branches are released one at a time, which is the worst case for the naive join: every
+//! release costs it a poll of each branch still waiting.
+//!
+//! Run with `cargo bench --bench join_all`.
+
+use std::{
+    cell::{Cell, RefCell},
+    future::Future,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+use combinators::comb::*;
+use combinators::executor::{block_on, yield_now};
+
+const BRANCHES: usize = 10_000;
+
+/// Joins by polling every pending branch whenever it is woken.
+struct NaiveJoinAll<F: Future> {
+    futures: Vec<Pin<Box<F>>>,
+    outputs: Vec<Option<F::Output>>,
+}
+
+fn naive_join_all<F: Future>(futures: impl IntoIterator<Item = F>) -> NaiveJoinAll<F> {
+    let futures: Vec<_> = futures.into_iter().map(Box::pin).collect();
+    let outputs = futures.iter().map(|_| None).collect();
+    NaiveJoinAll { futures, outputs }
+}
+
+impl<F: Future> Future for NaiveJoinAll<F> {
+    type Output = Vec<F::Output>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+
+        let mut all_done = true;
+        for (fut, out) in this.futures.iter_mut().zip(&mut this.outputs) {
+            if out.is_none() {
+                match fut.as_mut().poll(cx) {
+                    Poll::Ready(res) => *out = Some(res),
+                    Poll::Pending => all_done = false,
+                }
+            }
+        }
+
+        if all_done {
+            Poll::Ready(this.outputs.iter_mut().map(|out| out.take().unwrap()).collect())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+#[derive(Default)]
+struct Gate {
+    open: Cell<bool>,
+    waker: RefCell<Option<Waker>>,
+}
+
+fn wait(gate: Rc<Gate>, polls: Rc<Cell<usize>>) -> impl Future<Output = ()> {
+    std::future::poll_fn(move |cx| {
+        polls.set(polls.get() + 1);
+        if gate.open.get() {
+            return Poll::Ready(());
+        }
+        *gate.waker.borrow_mut() = Some(cx.waker().clone());
+        Poll::Pending
+    })
+}
+
+/// Opens the gates one by one, yielding in between so the join is polled after each release.
+async fn release(gates: Vec<Rc<Gate>>) {
+    for gate in gates {
+        gate.open.set(true);
+        if let Some(waker) = gate.waker.borrow_mut().take() {
+            waker.wake();
+        }
+        yield_now().await;
+    }
+}
+
+fn run<J, Fut>(join: J) -> (Duration, usize)
+where
+    J: FnOnce(Vec<Pin<Box<dyn Future<Output = ()>>>>) -> Fut,
+    Fut: Future,
+{
+    let gates: Vec<_> = (0..BRANCHES).map(|_| Rc::new(Gate::default())).collect();
+    let polls = Rc::new(Cell::new(0));
+    let branches = gates
+        .iter()
+        .map(|gate| Box::pin(wait(gate.clone(), polls.clone())) as Pin<Box<dyn Future<Output = ()>>>)
+        .collect();
+
+    let start = Instant::now();
+    block_on(join_futures(join(branches), release(gates)));
+    (start.elapsed(), polls.get())
+}
+
+fn main() {
+    let (naive, naive_polls) = run(naive_join_all);
+    let (queued, queued_polls) = run(join_all);
+
+    println!("{BRANCHES} branches released one at a time");
+    println!("  naive join:    {naive:>12?} {naive_polls:>12} branch polls");
+    println!("  join_all:      {queued:>12?} {queued_polls:>12} branch polls");
+}
@@ -0,0 +1,114 @@
+#![allow(warnings)]
+#![allow(unused)]
+
+use std::{pin::Pin, future::Future};
+
+use color_eyre::Report;
+use combinators::comb::*;
+
+mod common;
+
+#[tokio::main]
+async fn main() -> Result<(), Report> {
+    common::setup()?;
+
+    run().await;
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct SumMax(i32, i32);
+
+impl Unpin for SumMax {
+
+}
+
+impl Semigroup for SumMax {
+    fn combine(&self, other: &Self) -> Self {
+        SumMax(self.0 + other.0, i32::max(self.1, other.1))
+    }
+}
+
+impl Monoid for SumMax {
+    fn identity() -> Self {
+        SumMax(0, 0)
+    }
+}
+
+
+pub async fn run() {
+    let ids = vec![1, 2, 3, 4, 5];
+
+    let futures = ids.into_iter()
+                     .map(|id| Box::pin(to_sum_max(id)) as BoxFuture<'static, SumMax>)
+                     .collect::<Vec<_>>();
+
+    let initial_future: BoxFuture<'static, SumMax> = Box::pin(fut_id());
+    let mut combined_future = initial_future;
+
+    for future in futures {
+        combined_future = combine_boxed(combined_future, future);
+    }
+
+    let final_result = combined_future.await;
+    dbg!(final_result);
+}
+
+
+async fn to_sum_max(id: i32) -> SumMax {
+    let score = match get_score(id).await {
+        Ok(score) => {
+            // println!("{:?}", score);
+            score
+        },
+        Err(_) => 0, // Handle the error case, maybe log it or use a default value
+    };
+    SumMax(score, score)
+}
+#[cfg(feature = "http")]
+async fn get_score(id: i32) -> Result<i32, reqwest::Error> {
+    reqwest::get(format!("http://127.0.0.1:3000/score/{}", id))
+        .await?
+        .json::<i32>()
+        .await
+}
+
+
+#[cfg(not(feature = "http"))]
+async fn get_score(id: i32) -> Result<i32, i32> {
+    match id {
+        1 => Ok(45),
+        2 => Ok(25),
+        3 => Ok(37),
+        4 => Ok(14),
+        5 => Ok(61),
+        _ => Ok(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use combinators::executor::{block_on, yield_now};
+    use combinators::laws::*;
+
+    // Scores are never negative, which is what makes `SumMax(0, 0)` an identity for `max`
+    impl Arbitrary for SumMax {
+        fn arbitrary(g: &mut Gen) -> Self {
+            let score = g.range(0, 100) as i32;
+            SumMax(score + g.range(0, 100) as i32, score)
+        }
+    }
+
+    #[test]
+    fn sum_max_is_a_monoid() {
+        block_on(async {
+            let config = LawConfig::default();
+
+            monoid_identity::<SumMax>(&config).unwrap();
+            monoid_associativity::<SumMax>(&config).unwrap();
+            monoid_combine_futures::<SumMax>(&config).await.unwrap();
+        })
+    }
+}
@@ -0,0 +1,177 @@
+#![allow(warnings)]
+#![allow(unused)]
+
+use std::{future::Future, time::Duration};
+
+use color_eyre::Report;
+use combinators::comb::*;
+use combinators::profile;
+use combinators::timeline::{Recordable, Recorder};
+
+mod common;
+
+#[tokio::main]
+async fn main() -> Result<(), Report> {
+    common::setup()?;
+
+    run().await;
+
+    Ok(())
+}
+
+pub async fn run() {
+    let recorder = Recorder::new();
+    // Flags any labelled step whose poll holds the executor for more than 10ms
+    profile::set_global_threshold(Some(Duration::from_millis(10)));
+
+    let products = node(
+        &recorder,
+        "products",
+        join_futures_bimap(
+            step(&recorder, "product 1", get_product(1)),
+            step(&recorder, "product 2", get_product(2)),
+            get_product_price,
+            get_product_price,
+        ),
+    );
+
+    let discount = node(
+        &recorder,
+        "discount",
+        sequence(
+            leaf(apply_discount_code(123), "apply_discount_code"),
+            sequence(step(&recorder, "sleep5", sleep5()), leaf(get_discount(), "get_discount")),
+        ),
+    );
+
+    let info = shared(step(&recorder, "info", get_info()));
+
+    let discounted = node(
+        &recorder,
+        "discounted",
+        combine_with(products, discount, apply_discounts_to_products),
+    );
+
+
+    let pipeline = node(&recorder, "pipeline", map(join_futures(discounted, info.clone()), format_data));
+
+    // The shape of the pipeline, before anything has run
+    print!("{}", describe_tree(&pipeline));
+    write_dot(&pipeline);
+
+    let result = pipeline.await;
+    dbg!(result);
+
+    // The shop info was already fetched by the pipeline above, this reuses its output
+    let location = map(info, |info: Info| info.location).await;
+    dbg!(location);
+
+    // Which step to speed up to make the whole pipeline faster
+    println!("{}", recorder.critical_path());
+
+    for (label, polls) in profile::poll_histograms() {
+        println!("{label:<20} {:>3} polls, p99 {:>10?}, max {:>10?}", polls.count(), polls.quantile(0.99), polls.max());
+    }
+
+    if let Err(e) = export_timeline(&recorder) {
+        tracing::warn!("could not export the timeline: {e}");
+    }
+}
+
+/// Labels a combinator of the pipeline, both for tracing and on the recorded timeline.
+fn node<F>(recorder: &Recorder, label: &'static str, future: F) -> impl Future<Output = F::Output> + Describe
+where
+    F: Future + Describe,
+{
+    future.recorded(recorder, label).traced(label)
+}
+
+/// Like [`node`], for a plain future at the bottom of the pipeline.
+fn step<F: Future>(recorder: &Recorder, label: &'static str, future: F) -> impl Future<Output = F::Output> + Describe {
+    node(recorder, label, leaf(future, label))
+}
+
+fn write_dot(pipeline: &dyn Describe) {
+    let path = std::env::temp_dir().join("case2.dot");
+    match std::fs::write(&path, describe_dot(pipeline)) {
+        Ok(()) => tracing::info!("pipeline graph written to {}", path.display()),
+        Err(e) => tracing::warn!("could not write the pipeline graph: {e}"),
+    }
+}
+
+/// Writes the timeline next to the other temporary files, as a Chrome trace and a Gantt chart.
+fn export_timeline(recorder: &Recorder) -> std::io::Result<()> {
+    let dir = std::env::temp_dir();
+    let trace = dir.join("case2.trace.json");
+    let gantt = dir.join("case2.gantt.html");
+
+    std::fs::write(&trace, recorder.chrome_trace())?;
+    std::fs::write(&gantt, recorder.gantt_html())?;
+
+    tracing::info!("timeline written to {} and {}", trace.display(), gantt.display());
+    Ok(())
+}
+
+fn format_data(data: ((f32, f32), Info)) -> String {
+    let (prices, info) = data;
+
+    format!("Using discount, the prices for the products are {prices:?} from {0}", info.name)
+}
+
+#[derive(Debug)]
+struct Product {
+    code: i32,
+    price: f32,
+}
+
+fn apply_discounts_to_products(product: (f32, f32), discount: i32) -> (f32, f32) {
+    let (first, second) = product;
+
+    let first = first - (first * (discount as f32 / 100.0));
+    let second = second - (second * (discount as f32 / 100.0));
+
+    (first, second)
+}
+
+async fn get_product(id: i32) -> Product {
+    match id {
+        1 => Product {
+            code: 14,
+            price: 12.41,
+        },
+        2 => Product {
+            code: 123,
+            price: 41.74,
+        },
+        _ => panic!(),
+    }
+}
+
+async fn apply_discount_code(code: i32) -> () {}
+
+async fn get_discount() -> i32 {
+    14
+}
+
+fn get_product_price(product: Product) -> f32 {
+    product.price
+}
+
+async fn sleep5() {
+    tokio::time::sleep(Duration::from_secs(2)).await;
+}
+
+#[derive(Debug, Clone)]
+struct Info {
+    name: String,
+    code: i32,
+    location: String,
+}
+
+async fn get_info() -> Info {
+    Info {
+        code: 13,
+        location: String::from("Middle st. 15"),
+        name: String::from("Local shop"),
+    }
+}
@@ -1,43 +1,17 @@
 #![allow(warnings)]
 #![allow(unused)]
 
-
-use std::net::SocketAddr;
-use std::process::exit;
-use std::{future::Future, time::Duration};
-
 use color_eyre::Report;
-use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::TcpStream,
-};
-use tracing::info;
 use tracing_subscriber::EnvFilter;
 
-mod comb;
-mod examples;
-
-
-#[tokio::main]
-async fn main() -> Result<(), Report> {
-    setup()?;
-
-
-    examples::case1::run().await;
-    examples::case2::run().await;
-
-
-    Ok(())
-}
-
-
-
-fn setup() -> Result<(), Report> {
+pub fn setup() -> Result<(), Report> {
     if std::env::var("RUST_LIB_BACKTRACE").is_err() {
         std::env::set_var("RUST_LIB_BACKTRACE", "1")
     }
     color_eyre::install()?;
 
+    // Combinators wrapped with `.traced(label)` report their completion at `info`,
+    // `RUST_LOG=combinators=debug` adds the completion of every `join_all` branch
     if std::env::var("RUST_LOG").is_err() {
         std::env::set_var("RUST_LOG", "info")
     }
@@ -47,5 +21,3 @@ fn setup() -> Result<(), Report> {
 
     Ok(())
 }
-
-
@@ -1,30 +1,176 @@
-#![allow(warnings)]
-#![allow(unused)]
-
-use std::{
+use core::{
     future::Future,
     pin::Pin,
-    process::Output,
     task::{Context, Poll},
-    thread::JoinHandle,
 };
 
+#[cfg(feature = "alloc")]
+use alloc::{boxed::Box, string::String, vec::Vec};
+
+#[cfg(feature = "std")]
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::Waker,
+};
+
+#[cfg(feature = "tracing")]
 use tracing::info;
 
+#[cfg(feature = "alloc")]
+use crate::timer::Timer;
+#[cfg(feature = "tokio")]
+use crate::timer::TokioTimer;
+
+#[cfg(all(test, feature = "std"))]
 fn identity<T>(id: T) -> T {
     id
 }
 
+/// Order in which a join or select polls its branches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PollPolicy {
+    /// Always in the order the branches were given, so the first branch is favoured.
+    #[default]
+    Biased,
+    /// Each poll starts one branch further along than the previous one.
+    RoundRobin,
+    /// A fresh random order on every poll, drawn from a generator seeded with `seed`
+    /// so that a run can be replayed.
+    Random { seed: u64 },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct PollOrder {
+    policy: PollPolicy,
+    // Poll counter for `RoundRobin`, generator state for `Random`
+    state: u64,
+}
+
+impl PollOrder {
+    pub(crate) fn new(policy: PollPolicy) -> Self {
+        let state = match policy {
+            PollPolicy::Random { seed } => seed,
+            _ => 0,
+        };
+        PollOrder { policy, state }
+    }
+
+    // SplitMix64, same generator as `laws::Gen`
+    fn next_random(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Index of the branch to poll first, out of `len`.
+    fn first(&mut self, len: usize) -> usize {
+        match self.policy {
+            PollPolicy::Biased => 0,
+            PollPolicy::RoundRobin => {
+                let first = (self.state % len as u64) as usize;
+                self.state = self.state.wrapping_add(1);
+                first
+            }
+            PollPolicy::Random { .. } => (self.next_random() % len as u64) as usize,
+        }
+    }
+
+    /// Polls the two branches of a join in the order picked by the policy.
+    fn both<A, B>(&mut self, cx: &mut Context<'_>, a: A, b: B)
+    where
+        A: FnOnce(&mut Context<'_>),
+        B: FnOnce(&mut Context<'_>),
+    {
+        if self.first(2) == 0 {
+            a(cx);
+            b(cx);
+        } else {
+            b(cx);
+            a(cx);
+        }
+    }
+
+    /// Sorts the indices of branches out of `len` into the order they should be polled in.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn arrange(&mut self, indices: &mut [usize], len: usize) {
+        match self.policy {
+            PollPolicy::Biased => indices.sort_unstable(),
+            PollPolicy::RoundRobin => {
+                if len > 0 {
+                    let first = self.first(len);
+                    indices.sort_unstable_by_key(|&i| (i + len - first) % len);
+                }
+            }
+            PollPolicy::Random { .. } => {
+                for i in (1..indices.len()).rev() {
+                    let j = (self.next_random() % (i as u64 + 1)) as usize;
+                    indices.swap(i, j);
+                }
+            }
+        }
+    }
+}
+
+impl Default for PollOrder {
+    fn default() -> Self {
+        Self::new(PollPolicy::default())
+    }
+}
+
+/// When a combinator was first polled, so it can report how long each of its branches took.
+#[derive(Debug, Clone, Copy, Default)]
+struct BranchClock {
+    #[cfg(feature = "std")]
+    started: Option<std::time::Instant>,
+}
+
+impl BranchClock {
+    fn start(&mut self) {
+        #[cfg(feature = "std")]
+        self.started.get_or_insert_with(std::time::Instant::now);
+    }
+
+    /// Reports that branch `branch` of a `kind` combinator finished, `ok` being false when
+    /// it failed the whole combinator, like an error in a [`try_join`]. The branch's latency
+    /// and outcome go to the [`metrics`](crate::metrics) sink, and to `tracing` as an event.
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    fn completed(&self, kind: Kind, branch: usize, ok: bool) {
+        #[cfg(feature = "std")]
+        if let Some(started) = self.started {
+            let elapsed = started.elapsed();
+            crate::metrics::branch_completed(kind.as_str(), elapsed, ok);
+            #[cfg(feature = "tracing")]
+            tracing::debug!(combinator = kind.as_str(), branch, ok, ?elapsed, "branch completed");
+        }
+    }
+}
+
+/// Runs one poll of a `kind` combinator, timing it under the combinator's name when
+/// [`crate::profile::set_global_threshold`] turned profiling on.
+#[cfg(feature = "std")]
+fn profiled<T>(kind: Kind, poll: impl FnOnce() -> T) -> T {
+    crate::profile::timed(kind.as_str(), crate::profile::global_threshold(), poll)
+}
+
+#[cfg(not(feature = "std"))]
+fn profiled<T>(_kind: Kind, poll: impl FnOnce() -> T) -> T {
+    poll()
+}
+
 enum State<F, T, E>
 where
     F: Future<Output = Result<T, E>>,
 {
     Future(F),
     Ok(T),
-    Gone,
 }
 
-enum TryJoin<A, B, AR, BR, E>
+enum TryJoinState<A, B, AR, BR, E>
 where
     A: Future<Output = Result<AR, E>>,
     B: Future<Output = Result<BR, E>>,
@@ -36,14 +182,40 @@ where
     Done,
 }
 
-pub fn try_join<A, B, AR, BR, E>(a: A, b: B) -> impl Future<Output = Result<(AR, BR), E>>
+pub struct TryJoin<A, B, AR, BR, E>
+where
+    A: Future<Output = Result<AR, E>>,
+    B: Future<Output = Result<BR, E>>,
+{
+    state: TryJoinState<A, B, AR, BR, E>,
+    order: PollOrder,
+    clock: BranchClock,
+}
+
+pub fn try_join<A, B, AR, BR, E>(a: A, b: B) -> TryJoin<A, B, AR, BR, E>
+where
+    A: Future<Output = Result<AR, E>>,
+    B: Future<Output = Result<BR, E>>,
+{
+    TryJoin {
+        state: TryJoinState::Polling {
+            a: State::Future(a),
+            b: State::Future(b),
+        },
+        order: PollOrder::default(),
+        clock: BranchClock::default(),
+    }
+}
+
+impl<A, B, AR, BR, E> TryJoin<A, B, AR, BR, E>
 where
     A: Future<Output = Result<AR, E>>,
     B: Future<Output = Result<BR, E>>,
 {
-    TryJoin::Polling {
-        a: State::Future(a),
-        b: State::Future(b),
+    /// Sets the order the two branches are polled in.
+    pub fn policy(mut self, policy: PollPolicy) -> Self {
+        self.order = PollOrder::new(policy);
+        self
     }
 }
 
@@ -55,37 +227,65 @@ where
     type Output = Result<(AR, BR), E>;
 
     fn poll(
-        mut self: std::pin::Pin<&mut Self>,
-        cx: &mut std::task::Context<'_>,
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
     ) -> Poll<Self::Output> {
-        let this = unsafe { self.get_unchecked_mut() };
-        let (a, b) = match this {
-            TryJoin::Polling { a, b } => (a, b),
-            TryJoin::Done => panic!("TryJoined polled after completion"),
-        };
-
-        if let State::Future(fut) = a {
-            if let Poll::Ready(res) = unsafe { Pin::new_unchecked(fut) }.poll(cx) {
-                *a = State::Ok(res?);
-            }
-        }
+        profiled(Kind::TryJoin, || {
+            let this = unsafe { self.get_unchecked_mut() };
+            let (a, b) = match &mut this.state {
+                TryJoinState::Polling { a, b } => (a, b),
+                TryJoinState::Done => panic!("TryJoined polled after completion"),
+            };
+            this.clock.start();
+            let clock = &this.clock;
+
+            let mut poll_a = |cx: &mut Context<'_>| {
+                if let State::Future(fut) = a {
+                    if let Poll::Ready(res) = unsafe { Pin::new_unchecked(fut) }.poll(cx) {
+                        clock.completed(Kind::TryJoin, 0, res.is_ok());
+                        match res {
+                            Ok(res) => *a = State::Ok(res),
+                            Err(e) => return Err(e),
+                        }
+                    }
+                }
+                Ok(())
+            };
+            let mut poll_b = |cx: &mut Context<'_>| {
+                if let State::Future(fut) = b {
+                    if let Poll::Ready(res) = unsafe { Pin::new_unchecked(fut) }.poll(cx) {
+                        clock.completed(Kind::TryJoin, 1, res.is_ok());
+                        match res {
+                            Ok(res) => *b = State::Ok(res),
+                            Err(e) => return Err(e),
+                        }
+                    }
+                }
+                Ok(())
+            };
 
-        if let State::Future(fut) = b {
-            if let Poll::Ready(res) = unsafe { Pin::new_unchecked(fut) }.poll(cx) {
-                *b = State::Ok(res?);
+            // The first error wins, without polling the other branch again
+            let res = if this.order.first(2) == 0 {
+                poll_a(cx).and_then(|_| poll_b(cx))
+            } else {
+                poll_b(cx).and_then(|_| poll_a(cx))
+            };
+            res?;
+
+            match &this.state {
+                TryJoinState::Polling {
+                    a: State::Ok(_),
+                    b: State::Ok(_),
+                } => match core::mem::replace(&mut this.state, TryJoinState::Done) {
+                    TryJoinState::Polling {
+                        a: State::Ok(a),
+                        b: State::Ok(b),
+                    } => Ok((a, b)).into(),
+                    _ => unreachable!(),
+                },
+                _ => Poll::Pending,
             }
-        }
-
-        match (a, b) {
-            (State::Ok(_), State::Ok(_)) => match std::mem::replace(this, Self::Done) {
-                Self::Polling {
-                    a: State::Ok(a),
-                    b: State::Ok(b),
-                } => Ok((a, b)).into(),
-                _ => unreachable!(),
-            },
-            _ => Poll::Pending,
-        }
+        })
     }
 }
 
@@ -96,10 +296,26 @@ where
 {
     Future(F),
     Ok(T),
-    Gone,
 }
 
-enum JoinFutures<A, B, AR, BR>
+impl<F, T> SimpleState<F, T>
+where
+    F: Future<Output = T>,
+{
+    /// Polls the future if it hasn't completed yet, storing its output once it does.
+    /// Returns whether it completed during this call.
+    fn poll_in_place(&mut self, cx: &mut Context<'_>) -> bool {
+        if let SimpleState::Future(fut) = self {
+            if let Poll::Ready(res) = unsafe { Pin::new_unchecked(fut) }.poll(cx) {
+                *self = SimpleState::Ok(res);
+                return true;
+            }
+        }
+        false
+    }
+}
+
+enum JoinFuturesState<A, B, AR, BR>
 where
     A: Future<Output = AR>,
     B: Future<Output = BR>,
@@ -111,14 +327,40 @@ where
     Done,
 }
 
-pub fn join_futures<A, B, AR, BR>(a: A, b: B) -> impl Future<Output = (AR, BR)>
+pub struct JoinFutures<A, B, AR, BR>
+where
+    A: Future<Output = AR>,
+    B: Future<Output = BR>,
+{
+    state: JoinFuturesState<A, B, AR, BR>,
+    order: PollOrder,
+    clock: BranchClock,
+}
+
+pub fn join_futures<A, B, AR, BR>(a: A, b: B) -> JoinFutures<A, B, AR, BR>
+where
+    A: Future<Output = AR>,
+    B: Future<Output = BR>,
+{
+    JoinFutures {
+        state: JoinFuturesState::Polling {
+            a: SimpleState::Future(a),
+            b: SimpleState::Future(b),
+        },
+        order: PollOrder::default(),
+        clock: BranchClock::default(),
+    }
+}
+
+impl<A, B, AR, BR> JoinFutures<A, B, AR, BR>
 where
     A: Future<Output = AR>,
     B: Future<Output = BR>,
 {
-    JoinFutures::Polling {
-        a: SimpleState::Future(a),
-        b: SimpleState::Future(b),
+    /// Sets the order the two branches are polled in.
+    pub fn policy(mut self, policy: PollPolicy) -> Self {
+        self.order = PollOrder::new(policy);
+        self
     }
 }
 
@@ -129,39 +371,45 @@ where
 {
     type Output = (AR, BR);
 
-    fn poll(self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Self::Output> {
-        let this = unsafe { self.get_unchecked_mut() };
-        let (a, b) = match this {
-            JoinFutures::Polling { a, b } => (a, b),
-            _ => panic!("Join futures polled after completion"),
-        };
-
-        if let SimpleState::Future(fut) = a {
-            if let Poll::Ready(res) = unsafe { Pin::new_unchecked(fut) }.poll(cx) {
-                *a = SimpleState::Ok(res);
-            }
-        }
-
-        if let SimpleState::Future(fut) = b {
-            if let Poll::Ready(res) = unsafe { Pin::new_unchecked(fut) }.poll(cx) {
-                *b = SimpleState::Ok(res);
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        profiled(Kind::Join, || {
+            let this = unsafe { self.get_unchecked_mut() };
+            let (a, b) = match &mut this.state {
+                JoinFuturesState::Polling { a, b } => (a, b),
+                _ => panic!("Join futures polled after completion"),
+            };
+
+            this.clock.start();
+            let clock = &this.clock;
+            this.order.both(
+                cx,
+                |cx| {
+                    if a.poll_in_place(cx) {
+                        clock.completed(Kind::Join, 0, true);
+                    }
+                },
+                |cx| {
+                    if b.poll_in_place(cx) {
+                        clock.completed(Kind::Join, 1, true);
+                    }
+                },
+            );
+
+            match (a, b) {
+                (SimpleState::Ok(_), SimpleState::Ok(_)) => match core::mem::replace(&mut this.state, JoinFuturesState::Done) {
+                    JoinFuturesState::Polling {
+                        a: SimpleState::Ok(a),
+                        b: SimpleState::Ok(b),
+                    } => Poll::Ready((a, b)),
+                    _ => unreachable!(),
+                },
+                _ => Poll::Pending,
             }
-        }
-
-        match (a, b) {
-            (SimpleState::Ok(_), SimpleState::Ok(_)) => match std::mem::replace(this, Self::Done) {
-                JoinFutures::Polling {
-                    a: SimpleState::Ok(a),
-                    b: SimpleState::Ok(b),
-                } => Poll::Ready((a, b)),
-                _ => unreachable!(),
-            },
-            _ => Poll::Pending,
-        }
+        })
     }
 }
 
-enum JoinFuturesBMap<A, B, AR, BR, AR2, BR2, F, G>
+enum JoinFuturesBMapState<A, B, AR, BR, AR2, BR2, F, G>
 where
     A: Future<Output = AR>,
     B: Future<Output = BR>,
@@ -177,18 +425,48 @@ where
     Done,
 }
 
-pub fn join_futures_bimap<A, B, AR, BR, AR2, BR2, F, G>(a: A, b: B, f: F, g: G) -> impl Future<Output = (AR2, BR2)>
+pub struct JoinFuturesBMap<A, B, AR, BR, AR2, BR2, F, G>
+where
+    A: Future<Output = AR>,
+    B: Future<Output = BR>,
+    F: FnOnce(AR) -> AR2,
+    G: FnOnce(BR) -> BR2,
+{
+    state: JoinFuturesBMapState<A, B, AR, BR, AR2, BR2, F, G>,
+    order: PollOrder,
+    clock: BranchClock,
+}
+
+pub fn join_futures_bimap<A, B, AR, BR, AR2, BR2, F, G>(a: A, b: B, f: F, g: G) -> JoinFuturesBMap<A, B, AR, BR, AR2, BR2, F, G>
+where
+    A: Future<Output = AR>,
+    B: Future<Output = BR>,
+    F: FnOnce(AR) -> AR2,
+    G: FnOnce(BR) -> BR2,
+{
+    JoinFuturesBMap {
+        state: JoinFuturesBMapState::Polling {
+            a: SimpleState::Future(a),
+            b: SimpleState::Future(b),
+            f,
+            g
+        },
+        order: PollOrder::default(),
+        clock: BranchClock::default(),
+    }
+}
+
+impl<A, B, AR, BR, AR2, BR2, F, G> JoinFuturesBMap<A, B, AR, BR, AR2, BR2, F, G>
 where
     A: Future<Output = AR>,
     B: Future<Output = BR>,
     F: FnOnce(AR) -> AR2,
     G: FnOnce(BR) -> BR2,
 {
-    JoinFuturesBMap::Polling {
-        a: SimpleState::Future(a),
-        b: SimpleState::Future(b),
-        f,
-        g
+    /// Sets the order the two branches are polled in.
+    pub fn policy(mut self, policy: PollPolicy) -> Self {
+        self.order = PollOrder::new(policy);
+        self
     }
 }
 
@@ -201,109 +479,158 @@ where
 {
     type Output = (AR2, BR2);
 
-    fn poll(self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Self::Output> {
-        let this = unsafe { self.get_unchecked_mut() };
-        let (a, b, f, g) = match this {
-            JoinFuturesBMap::Polling { a, b, f, g } => (a, b, f, g),
-            _ => panic!("Join futures polled after completion"),
-        };
-
-        if let SimpleState::Future(fut) = a {
-            if let Poll::Ready(res) = unsafe { Pin::new_unchecked(fut) }.poll(cx) {
-                *a = SimpleState::Ok(res);
-            }
-        }
-
-        if let SimpleState::Future(fut) = b {
-            if let Poll::Ready(res) = unsafe { Pin::new_unchecked(fut) }.poll(cx) {
-                *b = SimpleState::Ok(res);
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        profiled(Kind::Join, || {
+            let this = unsafe { self.get_unchecked_mut() };
+            let (a, b) = match &mut this.state {
+                JoinFuturesBMapState::Polling { a, b, .. } => (a, b),
+                _ => panic!("Join futures polled after completion"),
+            };
+
+            this.clock.start();
+            let clock = &this.clock;
+            this.order.both(
+                cx,
+                |cx| {
+                    if a.poll_in_place(cx) {
+                        clock.completed(Kind::Join, 0, true);
+                    }
+                },
+                |cx| {
+                    if b.poll_in_place(cx) {
+                        clock.completed(Kind::Join, 1, true);
+                    }
+                },
+            );
+
+            match (a, b) {
+                (SimpleState::Ok(_), SimpleState::Ok(_)) => match core::mem::replace(&mut this.state, JoinFuturesBMapState::Done) {
+                    JoinFuturesBMapState::Polling {
+                        a: SimpleState::Ok(a),
+                        b: SimpleState::Ok(b),
+                        f,
+                        g
+                    } => Poll::Ready((f(a), g(b))),
+                    _ => unreachable!(),
+                },
+                _ => Poll::Pending,
             }
-        }
-
-        match (a, b) {
-            (SimpleState::Ok(_), SimpleState::Ok(_)) => match std::mem::replace(this, Self::Done) {
-                JoinFuturesBMap::Polling {
-                    a: SimpleState::Ok(a),
-                    b: SimpleState::Ok(b),
-                    f,
-                    g
-                } => Poll::Ready((f(a), g(b))),
-                _ => unreachable!(),
-            },
-            _ => Poll::Pending,
-        }
+        })
     }
 }
 
-enum Sequential<A, B, AR, BR>
+enum SequentialState<A, B, M>
 where
-    A: Future<Output = AR>,
-    B: Future<Output = BR>,
+    A: Future,
+    B: Future,
+    M: FnOnce(A::Output) -> B,
 {
-    Polling {
-        first: A,
-        second: Option<Box<dyn FnOnce(AR) -> B>>,
-    },
+    First { first: A, second_fn: Option<M> },
+    Second(B),
     Done,
 }
 
-pub fn sequential<A, B, AR, BR>(
-    first: A,
-    second_fn: impl FnOnce(AR) -> B + 'static,
-) -> impl Future<Output = BR>
+pub struct Sequential<A, B, M>
 where
-    A: Future<Output = AR>,
-    B: Future<Output = BR>,
+    A: Future,
+    B: Future,
+    M: FnOnce(A::Output) -> B,
+{
+    state: SequentialState<A, B, M>,
+    clock: BranchClock,
+}
+
+/// Runs `first`, then feeds its output to `second_fn` and runs the future it returns.
+pub fn sequential<A, B, M>(first: A, second_fn: M) -> Sequential<A, B, M>
+where
+    A: Future,
+    B: Future,
+    M: FnOnce(A::Output) -> B,
 {
-    Sequential::Polling {
-        first,
-        second: Some(Box::new(second_fn)),
+    Sequential {
+        state: SequentialState::First {
+            first,
+            second_fn: Some(second_fn),
+        },
+        clock: BranchClock::default(),
     }
 }
 
-impl<A, B, AR, BR> Future for Sequential<A, B, AR, BR>
+/// A boxed future that can be sent across threads.
+#[cfg(feature = "alloc")]
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// A boxed future for `!Send` futures, e.g. ones holding an `Rc`.
+#[cfg(feature = "alloc")]
+pub type LocalBoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
+/// [`sequential`] behind a box, for when the concrete type can't be named, e.g. when
+/// building a chain in a loop.
+#[cfg(feature = "alloc")]
+pub fn sequential_boxed<'a, A, B, M>(first: A, second_fn: M) -> BoxFuture<'a, B::Output>
+where
+    A: Future + Send + 'a,
+    B: Future + Send + 'a,
+    M: FnOnce(A::Output) -> B + Send + 'a,
+{
+    Box::pin(sequential(first, second_fn))
+}
+
+/// [`sequential_boxed`] for futures that are not `Send`.
+#[cfg(feature = "alloc")]
+pub fn sequential_boxed_local<'a, A, B, M>(first: A, second_fn: M) -> LocalBoxFuture<'a, B::Output>
 where
-    A: Future<Output = AR>,
-    B: Future<Output = BR>,
+    A: Future + 'a,
+    B: Future + 'a,
+    M: FnOnce(A::Output) -> B + 'a,
+{
+    Box::pin(sequential(first, second_fn))
+}
+
+impl<A, B, M> Future for Sequential<A, B, M>
+where
+    A: Future,
+    B: Future,
+    M: FnOnce(A::Output) -> B,
 {
-    type Output = BR;
+    type Output = B::Output;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        let this = unsafe { self.get_unchecked_mut() };
-        let (first, second_fn) = match this {
-            Sequential::Polling { first, second } => (first, second.take()),
-            Sequential::Done => panic!("Sequential polled after completion"),
-        };
+        profiled(Kind::Sequence, || {
+            let Sequential { state: this, clock } = unsafe { self.get_unchecked_mut() };
+            clock.start();
+
+            if let SequentialState::First { first, second_fn } = this {
+                let first = unsafe { Pin::new_unchecked(first) };
+                let res = match first.poll(cx) {
+                    Poll::Ready(res) => res,
+                    Poll::Pending => return Poll::Pending,
+                };
+                clock.completed(Kind::Sequence, 0, true);
+
+                let second_fn = second_fn.take().expect("Sequential continuation already taken");
+                // The first future is dropped here, the continuation's future takes its place
+                *this = SequentialState::Second(second_fn(res));
+            }
 
-        let first = unsafe { Pin::new_unchecked(first) };
-        match first.poll(cx) {
-            Poll::Ready(res) => {
-                if let Some(second_fn) = second_fn {
-                    let mut second = (second_fn)(res);
-                    let second = unsafe { Pin::new_unchecked(&mut second) };
-                    match second.poll(cx) {
-                        Poll::Ready(second_res) => {
-                            return Poll::Ready(second_res);
-                            // *this = Sequential::Done
-                            // Poll::Ready((res, second_res))
-                        }
-                        Poll::Pending => {
-                            return Poll::Pending;
-                            // this.second = Some(second_fn);
-                            // Poll::Pending
-                        }
-                    }
-                } else {
-                    // No second future function provided, should not happen
-                    unreachable!()
+            let second = match this {
+                SequentialState::Second(second) => second,
+                _ => panic!("Sequential polled after completion"),
+            };
+
+            match unsafe { Pin::new_unchecked(second) }.poll(cx) {
+                Poll::Ready(res) => {
+                    clock.completed(Kind::Sequence, 1, true);
+                    *this = SequentialState::Done;
+                    Poll::Ready(res)
                 }
+                Poll::Pending => Poll::Pending,
             }
-            Poll::Pending => Poll::Pending,
-        }
+        })
     }
 }
 
-enum Sequence<F, G, T, U>
+enum SequenceState<F, G, T, U>
 where
     F: Future<Output = T>,
     G: Future<Output = U>,
@@ -313,13 +640,25 @@ where
     Done,
 }
 
-pub fn sequence<F, G, T, U>(first: F, second: G) -> impl Future<Output = U>
+pub struct Sequence<F, G, T, U>
+where
+    F: Future<Output = T>,
+    G: Future<Output = U>,
+{
+    state: SequenceState<F, G, T, U>,
+    clock: BranchClock,
+}
+
+pub fn sequence<F, G, T, U>(first: F, second: G) -> Sequence<F, G, T, U>
 where
     F: Future<Output = T>,
     G: Future<Output = U>,
     // G: FnOnce(T) -> T,
 {
-    Sequence::Polling { first: SimpleState::Future(first), second:  SimpleState::Future(second) }
+    Sequence {
+        state: SequenceState::Polling { first: SimpleState::Future(first), second:  SimpleState::Future(second) },
+        clock: BranchClock::default(),
+    }
 }
 
 impl<F, G, T, U> Future for Sequence<F, G, T, U>
@@ -329,64 +668,69 @@ where
 {
     type Output = U;
 
-    fn poll(self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Self::Output> {
-        let this: &mut Sequence<F, G, T, U> = unsafe { self.get_unchecked_mut() };
-        let (a, b) = match this {
-            Self::Polling { first, second } => (first, second),
-            Self::Done => panic!("Sequential polled after completion"),
-        };
-
-
-        if let SimpleState::Future(fut) = a {
-            if let Poll::Ready(res) = unsafe { Pin::new_unchecked(fut) }.poll(cx) {
-                *a = SimpleState::Ok(res);
-            } else {
-                return Poll::Pending;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        profiled(Kind::Sequence, || {
+            let Sequence { state: this, clock } = unsafe { self.get_unchecked_mut() };
+            let (a, b) = match this {
+                SequenceState::Polling { first, second } => (first, second),
+                SequenceState::Done => panic!("Sequential polled after completion"),
+            };
+            clock.start();
+
+
+            if let SimpleState::Future(fut) = a {
+                if let Poll::Ready(res) = unsafe { Pin::new_unchecked(fut) }.poll(cx) {
+                    *a = SimpleState::Ok(res);
+                    clock.completed(Kind::Sequence, 0, true);
+                } else {
+                    return Poll::Pending;
+                }
             }
-        }
 
         
-        if let SimpleState::Future(fut) = b {
-            if let Poll::Ready(res) = unsafe { Pin::new_unchecked(fut) }.poll(cx) {
-                *b = SimpleState::Ok(res);
-            } else {
-                return Poll::Pending;
+            if let SimpleState::Future(fut) = b {
+                if let Poll::Ready(res) = unsafe { Pin::new_unchecked(fut) }.poll(cx) {
+                    *b = SimpleState::Ok(res);
+                    clock.completed(Kind::Sequence, 1, true);
+                } else {
+                    return Poll::Pending;
+                }
             }
-        }
         
 
-        match (a, b) {
-            (SimpleState::Ok(_), SimpleState::Ok(_)) => match std::mem::replace(this, Self::Done) {
-                Sequence::Polling {
-                    first: SimpleState::Ok(a),
-                    second: SimpleState::Ok(b),
-                } => Poll::Ready(b),
-                _ => unreachable!(),
-            },
-            _ => Poll::Pending,
-        }
-
-        // todo!()
-        // let first = unsafe { Pin::new_unchecked(first) };
-        // let res = match first.poll(cx) {
-        //     Poll::Ready(res) => res,
-        //     Poll::Pending => return Poll::Pending,
-        // };
-
-        // let second = unsafe { Pin::new_unchecked(second) };
-        // let res = match second.poll(cx) {
-        //     Poll::Ready(res) => res,
-        //     Poll::Pending => return Poll::Pending,
-        // };
+            match (a, b) {
+                (SimpleState::Ok(_), SimpleState::Ok(_)) => match core::mem::replace(this, SequenceState::Done) {
+                    SequenceState::Polling {
+                        first: SimpleState::Ok(_),
+                        second: SimpleState::Ok(b),
+                    } => Poll::Ready(b),
+                    _ => unreachable!(),
+                },
+                _ => Poll::Pending,
+            }
 
-        // match std::mem::replace(this, Self::Done) {
-        //     Sequence::Polling { first, second } => Poll::Ready(res),
-        //     _ => unreachable!(),
-        // }
+            // todo!()
+            // let first = unsafe { Pin::new_unchecked(first) };
+            // let res = match first.poll(cx) {
+            //     Poll::Ready(res) => res,
+            //     Poll::Pending => return Poll::Pending,
+            // };
+
+            // let second = unsafe { Pin::new_unchecked(second) };
+            // let res = match second.poll(cx) {
+            //     Poll::Ready(res) => res,
+            //     Poll::Pending => return Poll::Pending,
+            // };
+
+            // match core::mem::replace(this, Self::Done) {
+            //     Sequence::Polling { first, second } => Poll::Ready(res),
+            //     _ => unreachable!(),
+            // }
+        })
     }
 }
 
-enum Mapping<F, T, M, U>
+enum MappingState<F, T, M, U>
 where
     F: Future<Output = T>,
     M: Fn(T) -> U,
@@ -395,13 +739,24 @@ where
     Done,
 }
 
-pub fn map<T, U, F>(task: F, mapper: impl Fn(T) -> U) -> impl Future<Output = U>
+pub struct Mapping<F, T, M, U>
+where
+    F: Future<Output = T>,
+    M: Fn(T) -> U,
+{
+    state: MappingState<F, T, M, U>,
+}
+
+pub fn map<T, U, F, M>(task: F, mapper: M) -> Mapping<F, T, M, U>
 where
     F: Future<Output = T>,
+    M: Fn(T) -> U,
 {
-    Mapping::Polling {
-        future: task,
-        mapper,
+    Mapping {
+        state: MappingState::Polling {
+            future: task,
+            mapper,
+        },
     }
 }
 
@@ -412,27 +767,29 @@ where
 {
     type Output = U;
 
-    fn poll(self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Self::Output> {
-        let this = unsafe { self.get_unchecked_mut() };
-        let (first, second) = match this {
-            Self::Polling { future, mapper } => (future, mapper),
-            Self::Done => panic!("Sequential polled after completion"),
-        };
-
-        let first = unsafe { Pin::new_unchecked(first) };
-        let res = match first.poll(cx) {
-            Poll::Ready(res) => res,
-            Poll::Pending => return Poll::Pending,
-        };
-
-        match std::mem::replace(this, Self::Done) {
-            Mapping::Polling { future, mapper } => Poll::Ready(mapper(res)),
-            _ => unreachable!(),
-        }
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        profiled(Kind::Map, || {
+            let this = unsafe { &mut self.get_unchecked_mut().state };
+            let first = match this {
+                MappingState::Polling { future, .. } => future,
+                MappingState::Done => panic!("Sequential polled after completion"),
+            };
+
+            let first = unsafe { Pin::new_unchecked(first) };
+            let res = match first.poll(cx) {
+                Poll::Ready(res) => res,
+                Poll::Pending => return Poll::Pending,
+            };
+
+            match core::mem::replace(this, MappingState::Done) {
+                MappingState::Polling { mapper, .. } => Poll::Ready(mapper(res)),
+                _ => unreachable!(),
+            }
+        })
     }
 }
 
-enum CombineWith<A, B, AR, BR, M, MR>
+enum CombineWithState<A, B, AR, BR, M, MR>
 where
     A: Future<Output = AR>,
     B: Future<Output = BR>,
@@ -446,16 +803,44 @@ where
     Done,
 }
 
-pub fn combine_with<A, B, AR, BR, M, MR>(a: A, b: B, combine: M) -> impl Future<Output = MR>
+pub struct CombineWith<A, B, AR, BR, M, MR>
+where
+    A: Future<Output = AR>,
+    B: Future<Output = BR>,
+    M: FnOnce(AR, BR) -> MR,
+{
+    state: CombineWithState<A, B, AR, BR, M, MR>,
+    order: PollOrder,
+    clock: BranchClock,
+}
+
+pub fn combine_with<A, B, AR, BR, M, MR>(a: A, b: B, combine: M) -> CombineWith<A, B, AR, BR, M, MR>
+where
+    A: Future<Output = AR>,
+    B: Future<Output = BR>,
+    M: FnOnce(AR, BR) -> MR,
+{
+    CombineWith {
+        state: CombineWithState::Polling {
+            a: SimpleState::Future(a),
+            b: SimpleState::Future(b),
+            combine,
+        },
+        order: PollOrder::default(),
+        clock: BranchClock::default(),
+    }
+}
+
+impl<A, B, AR, BR, M, MR> CombineWith<A, B, AR, BR, M, MR>
 where
     A: Future<Output = AR>,
     B: Future<Output = BR>,
     M: FnOnce(AR, BR) -> MR,
 {
-    CombineWith::Polling {
-        a: SimpleState::Future(a),
-        b: SimpleState::Future(b),
-        combine,
+    /// Sets the order the two branches are polled in.
+    pub fn policy(mut self, policy: PollPolicy) -> Self {
+        self.order = PollOrder::new(policy);
+        self
     }
 }
 
@@ -467,173 +852,2919 @@ where
 {
     type Output = MR;
 
-    fn poll(self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Self::Output> {
-        let this = unsafe { self.get_unchecked_mut() };
-        let (a, b, _combine) = match this {
-            CombineWith::Polling { a, b, combine } => (a, b, combine),
-            _ => unreachable!(),
-        };
-
-        if let SimpleState::Future(fut) = a {
-            // println!("we poll a");
-            if let Poll::Ready(res) = unsafe { Pin::new_unchecked(fut) }.poll(cx) {
-                // println!("we ready a");
-                *a = SimpleState::Ok(res);
-            }
-        }
-
-        if let SimpleState::Future(fut) = b {
-            // println!("we poll b");
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        profiled(Kind::Combine, || {
+            let this = unsafe { self.get_unchecked_mut() };
+            let (a, b) = match &mut this.state {
+                CombineWithState::Polling { a, b, .. } => (a, b),
+                _ => unreachable!(),
+            };
+
+            this.clock.start();
+            let clock = &this.clock;
+            this.order.both(
+                cx,
+                |cx| {
+                    if a.poll_in_place(cx) {
+                        clock.completed(Kind::Combine, 0, true);
+                    }
+                },
+                |cx| {
+                    if b.poll_in_place(cx) {
+                        clock.completed(Kind::Combine, 1, true);
+                    }
+                },
+            );
+
+            match (a, b) {
+                (SimpleState::Ok(_), SimpleState::Ok(_)) => match core::mem::replace(&mut this.state, CombineWithState::Done) {
+                    CombineWithState::Polling {
+                        a: SimpleState::Ok(a),
+                        b: SimpleState::Ok(b),
+                        combine,
+                    } => Poll::Ready(combine(a, b)),
+                    _ => unreachable!(),
+                },
+                _ => Poll::Pending,
+            }
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+/// Indices of the [`JoinAll`] branches woken since the last poll, shared with their wakers.
+struct BranchQueue {
+    ready: Mutex<Vec<usize>>,
+    // Whether a branch is already in `ready`, so repeated wakes queue it once
+    queued: Box<[AtomicBool]>,
+    parent: Mutex<Option<Waker>>,
+}
+
+#[cfg(feature = "std")]
+impl BranchQueue {
+    fn new(len: usize) -> Self {
+        // Every branch needs a first poll
+        BranchQueue {
+            ready: Mutex::new((0..len).collect()),
+            queued: (0..len).map(|_| AtomicBool::new(true)).collect(),
+            parent: Mutex::new(None),
+        }
+    }
+
+    fn register(&self, waker: &Waker) {
+        match &mut *self.parent.lock().unwrap() {
+            Some(existing) if existing.will_wake(waker) => {}
+            parent => *parent = Some(waker.clone()),
+        }
+    }
+
+    fn push(&self, index: usize) {
+        if !self.queued[index].swap(true, Ordering::SeqCst) {
+            self.ready.lock().unwrap().push(index);
+        }
+    }
+
+    fn take_ready(&self) -> Vec<usize> {
+        let ready = core::mem::take(&mut *self.ready.lock().unwrap());
+        // Cleared before the branches are polled, so a wake during the poll queues them again
+        for &index in &ready {
+            self.queued[index].store(false, Ordering::SeqCst);
+        }
+        ready
+    }
+}
+
+#[cfg(feature = "std")]
+struct BranchWaker {
+    index: usize,
+    queue: Arc<BranchQueue>,
+}
+
+#[cfg(feature = "std")]
+impl std::task::Wake for BranchWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        let queue = &self.queue;
+        queue.push(self.index);
+
+        let parent = queue.parent.lock().unwrap().clone();
+        if let Some(parent) = parent {
+            parent.wake();
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+type Branches<F> = Pin<Box<[SimpleState<F, <F as Future>::Output>]>>;
+
+#[cfg(feature = "alloc")]
+pub struct JoinAll<F>
+where
+    F: Future,
+{
+    elems: Branches<F>,
+    pending: usize,
+    done: bool,
+    order: PollOrder,
+    budget: usize,
+    clock: BranchClock,
+    #[cfg(feature = "std")]
+    queue: Arc<BranchQueue>,
+    #[cfg(feature = "std")]
+    wakers: Box<[Waker]>,
+    // Branches left to poll in the current round when the budget ran out
+    #[cfg(not(feature = "std"))]
+    round: Vec<usize>,
+}
+
+#[cfg(feature = "alloc")]
+/// Branch polls a [`JoinAll`] does in a single `poll` before yielding back to the executor.
+pub const DEFAULT_POLL_BUDGET: usize = 128;
+
+#[cfg(feature = "alloc")]
+/// Polls every future of the iterator concurrently, resolving to their outputs in order.
+///
+/// With `std`, each branch gets its own waker and a wake only repolls the branches that
+/// were actually woken. Without it every pending branch is polled on each wake.
+///
+/// At most [`DEFAULT_POLL_BUDGET`] branches are polled per `poll` (see [`JoinAll::budget`]),
+/// after which the join wakes itself and yields so it doesn't starve other tasks.
+pub fn join_all<I>(iter: I) -> JoinAll<I::Item>
+where
+    I: IntoIterator,
+    I::Item: Future,
+{
+    let elems: Box<[_]> = iter.into_iter().map(SimpleState::Future).collect();
+    let len = elems.len();
+
+    #[cfg(feature = "std")]
+    let queue = Arc::new(BranchQueue::new(len));
+
+    JoinAll {
+        elems: Box::into_pin(elems),
+        pending: len,
+        done: false,
+        order: PollOrder::default(),
+        budget: DEFAULT_POLL_BUDGET,
+        clock: BranchClock::default(),
+        #[cfg(not(feature = "std"))]
+        round: Vec::new(),
+        #[cfg(feature = "std")]
+        wakers: (0..len)
+            .map(|index| {
+                Waker::from(Arc::new(BranchWaker {
+                    index,
+                    queue: queue.clone(),
+                }))
+            })
+            .collect(),
+        #[cfg(feature = "std")]
+        queue,
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<F> JoinAll<F>
+where
+    F: Future,
+{
+    /// Sets the order in which the branches woken since the last poll are polled.
+    pub fn policy(mut self, policy: PollPolicy) -> Self {
+        self.order = PollOrder::new(policy);
+        self
+    }
+
+    /// Sets how many branches are polled in a single `poll` before yielding.
+    pub fn budget(mut self, polls: usize) -> Self {
+        assert!(polls > 0, "JoinAll needs a budget of at least one poll");
+        self.budget = polls;
+        self
+    }
+
+    fn poll_branch(&mut self, index: usize, cx: &mut Context<'_>) {
+        let elems = unsafe { self.elems.as_mut().get_unchecked_mut() };
+        let elem = &mut elems[index];
+
+        if let SimpleState::Future(fut) = elem {
             if let Poll::Ready(res) = unsafe { Pin::new_unchecked(fut) }.poll(cx) {
-                // println!("we ready b");
-                *b = SimpleState::Ok(res);
+                *elem = SimpleState::Ok(res);
+                self.pending -= 1;
+                self.clock.completed(Kind::JoinAll, index, true);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<F> Future for JoinAll<F>
+where
+    F: Future,
+{
+    type Output = Vec<F::Output>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        profiled(Kind::JoinAll, || {
+            let this = unsafe { self.get_unchecked_mut() };
+            if this.done {
+                panic!("JoinAll polled after completion");
+            }
+
+            this.clock.start();
+
+            #[cfg(feature = "std")]
+            let exhausted = {
+                this.queue.register(cx.waker());
+                let mut ready = this.queue.take_ready();
+                this.order.arrange(&mut ready, this.elems.len());
+
+                let polled = ready.len().min(this.budget);
+                for &index in &ready[..polled] {
+                    let waker = this.wakers[index].clone();
+                    this.poll_branch(index, &mut Context::from_waker(&waker));
+                }
+
+                // Left for the next poll
+                for &index in &ready[polled..] {
+                    this.queue.push(index);
+                }
+                polled < ready.len()
+            };
+
+            #[cfg(not(feature = "std"))]
+            let exhausted = {
+                let mut round = core::mem::take(&mut this.round);
+                if round.is_empty() {
+                    round = (0..this.elems.len()).collect();
+                    this.order.arrange(&mut round, this.elems.len());
+                }
+
+                let polled = round.len().min(this.budget);
+                for &index in &round[..polled] {
+                    this.poll_branch(index, cx);
+                }
+
+                round.drain(..polled);
+                this.round = round;
+                !this.round.is_empty()
+            };
+
+            if this.pending > 0 {
+                if exhausted {
+                    cx.waker().wake_by_ref();
+                }
+                return Poll::Pending;
+            }
+
+            // Every future has been replaced by its output, so nothing pinned is moved out here
+            this.done = true;
+            let elems = core::mem::replace(&mut this.elems, Box::into_pin(Box::new([]) as Box<[_]>));
+            let elems = unsafe { Pin::into_inner_unchecked(elems) };
+            let outputs = elems
+                .into_vec()
+                .into_iter()
+                .map(|elem| match elem {
+                    SimpleState::Ok(res) => res,
+                    _ => unreachable!(),
+                })
+                .collect();
+
+            Poll::Ready(outputs)
+        })
+    }
+}
+
+#[cfg(feature = "alloc")]
+/// Waits for every future to finish, keeping each branch's result instead of stopping
+/// at the first error (`Promise.allSettled`).
+pub fn join_settled<I, T, E>(iter: I) -> JoinAll<I::Item>
+where
+    I: IntoIterator,
+    I::Item: Future<Output = Result<T, E>>,
+{
+    join_all(iter)
+}
+
+#[cfg(feature = "std")]
+/// The panic a future raised while being polled, with its message extracted when the
+/// payload was a string (as it is for `panic!` with a message).
+pub struct PanicPayload {
+    message: Option<String>,
+    payload: Box<dyn std::any::Any + Send>,
+}
+
+#[cfg(feature = "std")]
+impl PanicPayload {
+    pub(crate) fn new(payload: Box<dyn std::any::Any + Send>) -> Self {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned());
+
+        PanicPayload { message, payload }
+    }
+
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    /// Continues unwinding with the original payload.
+    pub fn resume(self) -> ! {
+        std::panic::resume_unwind(self.payload)
+    }
+
+    pub fn into_inner(self) -> Box<dyn std::any::Any + Send> {
+        self.payload
+    }
+}
+
+// SAFETY: the payload is only reachable through `self` by value (`resume`, `into_inner`),
+// never through a shared reference, so sharing `&PanicPayload` between threads can't
+// observe it. Everything else in the struct is `Sync`.
+#[cfg(feature = "std")]
+unsafe impl Sync for PanicPayload {}
+
+#[cfg(feature = "std")]
+impl core::fmt::Debug for PanicPayload {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("PanicPayload")
+            .field(&self.message.as_deref().unwrap_or("Box<dyn Any>"))
+            .finish()
+    }
+}
+
+#[cfg(feature = "std")]
+impl core::fmt::Display for PanicPayload {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match &self.message {
+            Some(message) => write!(f, "future panicked: {message}"),
+            None => write!(f, "future panicked"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for PanicPayload {}
+
+#[cfg(feature = "std")]
+pub struct CatchUnwind<F>
+where
+    F: Future,
+{
+    future: F,
+}
+
+#[cfg(feature = "std")]
+/// Converts a panic raised while polling `future` into an `Err(PanicPayload)`.
+pub fn catch_unwind<F>(future: F) -> CatchUnwind<F>
+where
+    F: Future,
+{
+    CatchUnwind { future }
+}
+
+#[cfg(feature = "std")]
+impl<F> Future for CatchUnwind<F>
+where
+    F: Future,
+{
+    type Output = Result<F::Output, PanicPayload>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        profiled(Kind::CatchUnwind, || {
+            let future = unsafe { self.map_unchecked_mut(|this| &mut this.future) };
+            // A future that panicked is never polled again, so observing its broken state is not possible
+            match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| future.poll(cx))) {
+                Ok(Poll::Ready(res)) => Poll::Ready(Ok(res)),
+                Ok(Poll::Pending) => Poll::Pending,
+                Err(payload) => Poll::Ready(Err(PanicPayload::new(payload))),
             }
+        })
+    }
+}
+
+// Isolated variants of the joins: every branch is wrapped in `catch_unwind`, so a panic in
+// one branch is reported as that branch's result while the others run to completion.
+
+#[cfg(feature = "std")]
+/// Output of a branch wrapped in [`catch_unwind`].
+pub type Caught<F> = Result<<F as Future>::Output, PanicPayload>;
+
+#[cfg(feature = "std")]
+pub type JoinFuturesIsolated<A, B> = JoinFutures<CatchUnwind<A>, CatchUnwind<B>, Caught<A>, Caught<B>>;
+
+#[cfg(feature = "std")]
+pub type JoinFuturesBMapIsolated<A, B, AR2, BR2, F, G> =
+    JoinFuturesBMap<CatchUnwind<A>, CatchUnwind<B>, Caught<A>, Caught<B>, AR2, BR2, F, G>;
+
+#[cfg(feature = "std")]
+pub type CombineWithIsolated<A, B, M, MR> = CombineWith<CatchUnwind<A>, CatchUnwind<B>, Caught<A>, Caught<B>, M, MR>;
+
+#[cfg(feature = "std")]
+pub type CombineIsolated<F1, F2> = CombineWithIsolated<
+    F1,
+    F2,
+    fn(Caught<F1>, Caught<F2>) -> Caught<F1>,
+    Caught<F1>,
+>;
+
+#[cfg(feature = "std")]
+pub fn join_futures_isolated<A, B>(a: A, b: B) -> JoinFuturesIsolated<A, B>
+where
+    A: Future,
+    B: Future,
+{
+    join_futures(catch_unwind(a), catch_unwind(b))
+}
+
+#[cfg(feature = "std")]
+pub fn join_futures_bimap_isolated<A, B, AR2, BR2, F, G>(
+    a: A,
+    b: B,
+    f: F,
+    g: G,
+) -> JoinFuturesBMapIsolated<A, B, AR2, BR2, F, G>
+where
+    A: Future,
+    B: Future,
+    F: FnOnce(Result<A::Output, PanicPayload>) -> AR2,
+    G: FnOnce(Result<B::Output, PanicPayload>) -> BR2,
+{
+    join_futures_bimap(catch_unwind(a), catch_unwind(b), f, g)
+}
+
+#[cfg(feature = "std")]
+pub fn combine_with_isolated<A, B, M, MR>(
+    a: A,
+    b: B,
+    combine: M,
+) -> CombineWithIsolated<A, B, M, MR>
+where
+    A: Future,
+    B: Future,
+    M: FnOnce(Caught<A>, Caught<B>) -> MR,
+{
+    combine_with(catch_unwind(a), catch_unwind(b), combine)
+}
+
+#[cfg(feature = "std")]
+/// [`combine`] where both branches always run to completion. If either panicked, the
+/// result is the first panic instead of the combined outputs.
+pub fn combine_isolated<F1, F2>(future1: F1, future2: F2) -> CombineIsolated<F1, F2>
+where
+    F1: Future,
+    F2: Future<Output = F1::Output>,
+    F1::Output: Monoid,
+{
+    combine_with(catch_unwind(future1), catch_unwind(future2), combine_caught as fn(_, _) -> _)
+}
+
+#[cfg(feature = "std")]
+fn combine_caught<T: Monoid>(a: Result<T, PanicPayload>, b: Result<T, PanicPayload>) -> Result<T, PanicPayload> {
+    Ok(a?.combine(&b?))
+}
+
+#[cfg(feature = "std")]
+/// A branch of [`try_join_isolated`]: `F` with a panic turned into its error.
+pub type FlattenPanic<F, T, E> =
+    Mapping<CatchUnwind<F>, Result<Result<T, E>, PanicPayload>, fn(Result<Result<T, E>, PanicPayload>) -> Result<T, E>, Result<T, E>>;
+
+#[cfg(feature = "std")]
+pub type TryJoinIsolated<A, B, AR, BR, E> = TryJoin<FlattenPanic<A, AR, E>, FlattenPanic<B, BR, E>, AR, BR, E>;
+
+#[cfg(feature = "std")]
+/// [`try_join`] where a panicking branch fails the join with its payload converted into `E`.
+pub fn try_join_isolated<A, B, AR, BR, E>(a: A, b: B) -> TryJoinIsolated<A, B, AR, BR, E>
+where
+    A: Future<Output = Result<AR, E>>,
+    B: Future<Output = Result<BR, E>>,
+    E: From<PanicPayload>,
+{
+    try_join(
+        map(catch_unwind(a), flatten_panic as fn(_) -> _),
+        map(catch_unwind(b), flatten_panic as fn(_) -> _),
+    )
+}
+
+#[cfg(feature = "std")]
+pub type ValidateIsolated<A, B, AR, BR, E> = Validate<FlattenPanic<A, AR, E>, FlattenPanic<B, BR, E>, AR, BR, E>;
+
+#[cfg(feature = "std")]
+/// [`validate`] where a panicking branch counts as failed with its payload converted into `E`,
+/// merged with the other branch's error if it failed too.
+pub fn validate_isolated<A, B, AR, BR, E>(a: A, b: B) -> ValidateIsolated<A, B, AR, BR, E>
+where
+    A: Future<Output = Result<AR, E>>,
+    B: Future<Output = Result<BR, E>>,
+    E: Semigroup + From<PanicPayload>,
+{
+    validate(
+        map(catch_unwind(a), flatten_panic as fn(_) -> _),
+        map(catch_unwind(b), flatten_panic as fn(_) -> _),
+    )
+}
+
+#[cfg(feature = "std")]
+/// [`validate_all`] where a panicking branch is reported among the errors, converted into `E`.
+pub fn validate_all_isolated<I, T, E>(iter: I) -> ValidateAll<FlattenPanic<I::Item, T, E>>
+where
+    I: IntoIterator,
+    I::Item: Future<Output = Result<T, E>>,
+    E: From<PanicPayload>,
+{
+    validate_all(
+        iter.into_iter()
+            .map(|future| map(catch_unwind(future), flatten_panic as fn(_) -> _)),
+    )
+}
+
+#[cfg(feature = "std")]
+fn flatten_panic<T, E: From<PanicPayload>>(res: Result<Result<T, E>, PanicPayload>) -> Result<T, E> {
+    res.unwrap_or_else(|panic| Err(E::from(panic)))
+}
+
+#[cfg(feature = "std")]
+pub fn join_all_isolated<I>(iter: I) -> JoinAll<CatchUnwind<I::Item>>
+where
+    I: IntoIterator,
+    I::Item: Future,
+{
+    join_all(iter.into_iter().map(catch_unwind))
+}
+
+#[cfg(feature = "std")]
+/// Result of a single branch of [`join_settled_catching`].
+#[derive(Debug)]
+pub enum Outcome<T, E> {
+    Ok(T),
+    Err(E),
+    Panicked(PanicPayload),
+}
+
+#[cfg(feature = "std")]
+impl<T, E> Outcome<T, E> {
+    pub fn is_ok(&self) -> bool {
+        matches!(self, Outcome::Ok(_))
+    }
+
+    pub fn is_panicked(&self) -> bool {
+        matches!(self, Outcome::Panicked(_))
+    }
+
+    pub fn ok(self) -> Option<T> {
+        match self {
+            Outcome::Ok(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T, E> From<Result<Result<T, E>, PanicPayload>> for Outcome<T, E> {
+    fn from(res: Result<Result<T, E>, PanicPayload>) -> Self {
+        match res {
+            Ok(Ok(value)) => Outcome::Ok(value),
+            Ok(Err(e)) => Outcome::Err(e),
+            Err(panic) => Outcome::Panicked(panic),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+pub struct JoinSettledCatching<F>
+where
+    F: Future,
+{
+    join: JoinAll<CatchUnwind<F>>,
+}
+
+#[cfg(feature = "std")]
+/// Like [`join_settled`], but a branch panicking is reported as [`Outcome::Panicked`]
+/// while the remaining branches keep running.
+pub fn join_settled_catching<I, T, E>(iter: I) -> JoinSettledCatching<I::Item>
+where
+    I: IntoIterator,
+    I::Item: Future<Output = Result<T, E>>,
+{
+    JoinSettledCatching {
+        join: join_all(iter.into_iter().map(catch_unwind)),
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F> JoinSettledCatching<F>
+where
+    F: Future,
+{
+    /// See [`JoinAll::policy`].
+    pub fn policy(mut self, policy: PollPolicy) -> Self {
+        self.join = self.join.policy(policy);
+        self
+    }
+
+    /// See [`JoinAll::budget`].
+    pub fn budget(mut self, polls: usize) -> Self {
+        self.join = self.join.budget(polls);
+        self
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F, T, E> Future for JoinSettledCatching<F>
+where
+    F: Future<Output = Result<T, E>>,
+{
+    type Output = Vec<Outcome<T, E>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let join = unsafe { self.map_unchecked_mut(|this| &mut this.join) };
+        join.poll(cx).map(|results| results.into_iter().map(Outcome::from).collect())
+    }
+}
+
+#[cfg(feature = "std")]
+struct CancelInner {
+    cancelled: AtomicBool,
+    next_id: AtomicUsize,
+    wakers: Mutex<Vec<(usize, Waker)>>,
+}
+
+#[cfg(feature = "std")]
+impl CancelInner {
+    fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        let wakers = core::mem::take(&mut *self.wakers.lock().unwrap());
+        for (_, waker) in wakers {
+            waker.wake();
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    fn register(&self, id: usize, waker: &Waker) {
+        let mut wakers = self.wakers.lock().unwrap();
+        match wakers.iter_mut().find(|(other, _)| *other == id) {
+            Some((_, existing)) if existing.will_wake(waker) => {}
+            Some((_, existing)) => *existing = waker.clone(),
+            None => wakers.push((id, waker.clone())),
+        }
+    }
+
+    fn unregister(&self, id: usize) {
+        self.wakers.lock().unwrap().retain(|(other, _)| *other != id);
+    }
+}
+
+#[cfg(feature = "std")]
+/// Observes whether the combinator tree it was handed to has been cancelled.
+/// Clones share the same cancellation state.
+#[derive(Clone)]
+pub struct CancellationToken {
+    inner: Arc<CancelInner>,
+}
+
+#[cfg(feature = "std")]
+/// Cancels every [`Abortable`] created with the paired [`CancellationToken`].
+#[derive(Clone)]
+pub struct AbortHandle {
+    inner: Arc<CancelInner>,
+}
+
+#[cfg(feature = "std")]
+pub fn abort_pair() -> (AbortHandle, CancellationToken) {
+    let token = CancellationToken::new();
+    (token.abort_handle(), token)
+}
+
+#[cfg(feature = "std")]
+impl CancellationToken {
+    pub fn new() -> Self {
+        CancellationToken {
+            inner: Arc::new(CancelInner {
+                cancelled: AtomicBool::new(false),
+                next_id: AtomicUsize::new(0),
+                wakers: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    pub fn abort_handle(&self) -> AbortHandle {
+        AbortHandle {
+            inner: self.inner.clone(),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.inner.cancel();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.is_cancelled()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for CancellationToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl core::fmt::Debug for CancellationToken {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("CancellationToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+#[cfg(feature = "std")]
+impl AbortHandle {
+    pub fn abort(&self) {
+        self.inner.cancel();
+    }
+
+    pub fn is_aborted(&self) -> bool {
+        self.inner.is_cancelled()
+    }
+}
+
+#[cfg(feature = "std")]
+impl core::fmt::Debug for AbortHandle {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("AbortHandle")
+            .field("aborted", &self.is_aborted())
+            .finish()
+    }
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "derive", derive(serde::Serialize, serde::Deserialize))]
+pub struct Aborted;
+
+#[cfg(feature = "std")]
+impl core::fmt::Display for Aborted {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "future was aborted")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Aborted {}
+
+#[cfg(feature = "std")]
+enum AbortableState<F> {
+    Polling(F),
+    Done,
+}
+
+#[cfg(feature = "std")]
+pub struct Abortable<F>
+where
+    F: Future,
+{
+    state: AbortableState<F>,
+    token: CancellationToken,
+    id: usize,
+}
+
+#[cfg(feature = "std")]
+/// Runs `future` until it completes or `token` is cancelled, whichever comes first.
+///
+/// On cancellation the wrapped future is dropped on the spot, and with it every combinator
+/// nested inside it (`join_futures`, `sequence`, `combine_with`, ...), so the whole tree
+/// is torn down promptly and the result is `Err(Aborted)`.
+pub fn abortable<F>(future: F, token: CancellationToken) -> Abortable<F>
+where
+    F: Future,
+{
+    let id = token
+        .inner
+        .next_id
+        .fetch_add(1, Ordering::Relaxed);
+
+    Abortable {
+        state: AbortableState::Polling(future),
+        token,
+        id,
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F> Future for Abortable<F>
+where
+    F: Future,
+{
+    type Output = Result<F::Output, Aborted>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        profiled(Kind::Abortable, || {
+            let this = unsafe { self.get_unchecked_mut() };
+            let future = match &mut this.state {
+                AbortableState::Polling(future) => future,
+                AbortableState::Done => panic!("Abortable polled after completion"),
+            };
+
+            if this.token.is_cancelled() {
+                this.state = AbortableState::Done;
+                return Poll::Ready(Err(Aborted));
+            }
+
+            // Register before polling so a cancel racing with the poll still wakes us
+            this.token.inner.register(this.id, cx.waker());
+
+            match unsafe { Pin::new_unchecked(future) }.poll(cx) {
+                Poll::Ready(res) => {
+                    this.state = AbortableState::Done;
+                    this.token.inner.unregister(this.id);
+                    Poll::Ready(Ok(res))
+                }
+                Poll::Pending if this.token.is_cancelled() => {
+                    this.state = AbortableState::Done;
+                    Poll::Ready(Err(Aborted))
+                }
+                Poll::Pending => Poll::Pending,
+            }
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F> Drop for Abortable<F>
+where
+    F: Future,
+{
+    fn drop(&mut self) {
+        self.token.inner.unregister(self.id);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "derive", derive(serde::Serialize, serde::Deserialize))]
+pub enum Either<L, R> {
+    Left(L),
+    Right(R),
+}
+
+pub struct Select<A, B>
+where
+    A: Future,
+    B: Future,
+{
+    a: A,
+    b: B,
+    done: bool,
+    order: PollOrder,
+    clock: BranchClock,
+}
+
+/// Resolves with the output of whichever future finishes first. The other future is
+/// dropped together with the `Select`. By default `a` is polled first, so it wins a tie,
+/// see [`Select::policy`].
+pub fn select<A, B>(a: A, b: B) -> Select<A, B>
+where
+    A: Future,
+    B: Future,
+{
+    Select {
+        a,
+        b,
+        done: false,
+        order: PollOrder::default(),
+        clock: BranchClock::default(),
+    }
+}
+
+impl<A, B> Select<A, B>
+where
+    A: Future,
+    B: Future,
+{
+    /// Sets the order the two futures are polled in, and so which one wins a tie.
+    pub fn policy(mut self, policy: PollPolicy) -> Self {
+        self.order = PollOrder::new(policy);
+        self
+    }
+}
+
+impl<A, B> Future for Select<A, B>
+where
+    A: Future,
+    B: Future,
+{
+    type Output = Either<A::Output, B::Output>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        profiled(Kind::Select, || {
+            let this = unsafe { self.get_unchecked_mut() };
+            if this.done {
+                panic!("Select polled after completion");
+            }
+
+            this.clock.start();
+
+            let first = this.order.first(2);
+            for branch in [first, 1 - first] {
+                let res = match branch {
+                    0 => unsafe { Pin::new_unchecked(&mut this.a) }.poll(cx).map(Either::Left),
+                    _ => unsafe { Pin::new_unchecked(&mut this.b) }.poll(cx).map(Either::Right),
+                };
+
+                if res.is_ready() {
+                    this.clock.completed(Kind::Select, branch, true);
+                    this.done = true;
+                    return res;
+                }
+            }
+
+            Poll::Pending
+        })
+    }
+}
+
+#[cfg(feature = "alloc")]
+/// A growable race: resolves with the first successful output among its futures, or with
+/// the last error once every future pushed so far has failed. Futures still running when
+/// it resolves are dropped along with it.
+pub struct RaceOk<F> {
+    futures: Vec<Pin<Box<F>>>,
+    order: PollOrder,
+    clock: BranchClock,
+}
+
+#[cfg(feature = "alloc")]
+pub fn race_ok<I>(iter: I) -> RaceOk<I::Item>
+where
+    I: IntoIterator,
+{
+    RaceOk {
+        futures: iter.into_iter().map(Box::pin).collect(),
+        order: PollOrder::default(),
+        clock: BranchClock::default(),
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<F> RaceOk<F> {
+    /// Sets the order the futures are polled in, and so which one wins when several succeed
+    /// during the same poll.
+    pub fn policy(mut self, policy: PollPolicy) -> Self {
+        self.order = PollOrder::new(policy);
+        self
+    }
+
+    pub fn push(&mut self, future: F) {
+        self.futures.push(Box::pin(future));
+    }
+
+    pub fn len(&self) -> usize {
+        self.futures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.futures.is_empty()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<F, T, E> Future for RaceOk<F>
+where
+    F: Future<Output = Result<T, E>>,
+{
+    type Output = Result<T, E>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        profiled(Kind::Race, || {
+            assert!(!self.futures.is_empty(), "RaceOk polled without any futures");
+
+            let this = &mut *self;
+            this.clock.start();
+            let mut indices: Vec<usize> = (0..this.futures.len()).collect();
+            this.order.arrange(&mut indices, this.futures.len());
+
+            let mut last_error = None;
+            let mut failed = Vec::new();
+            for i in indices {
+                match this.futures[i].as_mut().poll(cx) {
+                    Poll::Ready(Ok(res)) => {
+                        this.clock.completed(Kind::Race, i, true);
+                        this.futures.clear();
+                        return Poll::Ready(Ok(res));
+                    }
+                    Poll::Ready(Err(e)) => {
+                        this.clock.completed(Kind::Race, i, false);
+                        failed.push(i);
+                        last_error = Some(e);
+                    }
+                    Poll::Pending => {}
+                }
+            }
+
+            // Removed back to front so the remaining indices stay valid
+            failed.sort_unstable();
+            for i in failed.into_iter().rev() {
+                drop(this.futures.remove(i));
+            }
+
+            match last_error {
+                Some(e) if self.futures.is_empty() => Poll::Ready(Err(e)),
+                _ => Poll::Pending,
+            }
+        })
+    }
+}
+
+#[cfg(feature = "tokio")]
+/// [`hedge_with_timer`] on tokio's timer.
+pub fn hedge<Fac, F, T, E>(factory: Fac, delay: core::time::Duration, max_copies: usize) -> Hedge<TokioTimer, Fac, F>
+where
+    Fac: FnMut() -> F,
+    F: Future<Output = Result<T, E>>,
+{
+    hedge_with_timer(TokioTimer, factory, delay, max_copies)
+}
+
+#[cfg(feature = "alloc")]
+/// Attempts launched by [`hedge_with_timer`], raced against each other.
+pub struct Hedge<Tm, Fac, F>
+where
+    Tm: Timer,
+{
+    timer: Tm,
+    factory: Fac,
+    delay: core::time::Duration,
+    max_copies: usize,
+    launched: usize,
+    attempts: RaceOk<F>,
+    // Started once an attempt is launched, and dropped when the next one is
+    sleep: Option<Pin<Box<Tm::Sleep>>>,
+}
+
+#[cfg(feature = "alloc")]
+/// Starts an attempt, and every time `delay` passes without a success launches another
+/// copy, launching at most `max_copies` attempts in total. Resolves with the first successful
+/// result, dropping the attempts that are still running. Once every attempt launched so far
+/// has failed, the next copy is launched without waiting for the delay; if all `max_copies`
+/// fail, the last error is returned.
+pub fn hedge_with_timer<Tm, Fac, F, T, E>(
+    timer: Tm,
+    mut factory: Fac,
+    delay: core::time::Duration,
+    max_copies: usize,
+) -> Hedge<Tm, Fac, F>
+where
+    Tm: Timer,
+    Fac: FnMut() -> F,
+    F: Future<Output = Result<T, E>>,
+{
+    assert!(max_copies > 0, "hedge needs at least one attempt");
+
+    Hedge {
+        attempts: race_ok(Some(factory())),
+        launched: 1,
+        timer,
+        factory,
+        delay,
+        max_copies,
+        sleep: None,
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<Tm, Fac, F> Hedge<Tm, Fac, F>
+where
+    Tm: Timer,
+{
+    /// Sets the order the attempts are polled in, see [`RaceOk::policy`].
+    pub fn policy(mut self, policy: PollPolicy) -> Self {
+        self.attempts = self.attempts.policy(policy);
+        self
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<Tm, Fac, F, T, E> Future for Hedge<Tm, Fac, F>
+where
+    Tm: Timer,
+    Fac: FnMut() -> F,
+    F: Future<Output = Result<T, E>>,
+{
+    type Output = Result<T, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Neither the timer nor the factory is pinned, the attempts and the sleep are boxed
+        let this = unsafe { self.get_unchecked_mut() };
+
+        loop {
+            match Pin::new(&mut this.attempts).poll(cx) {
+                Poll::Ready(Ok(res)) => return Poll::Ready(Ok(res)),
+                Poll::Ready(Err(e)) if this.launched == this.max_copies => return Poll::Ready(Err(e)),
+                Poll::Ready(Err(_)) => {
+                    // Every attempt so far failed, retry without waiting for the delay
+                    this.attempts.push((this.factory)());
+                    this.launched += 1;
+                    this.sleep = None;
+                    #[cfg(feature = "std")]
+                    crate::metrics::increment(crate::metrics::RETRIES, &[("combinator", "hedge"), ("reason", "error")]);
+                    continue;
+                }
+                Poll::Pending => {}
+            }
+
+            if this.launched == this.max_copies {
+                return Poll::Pending;
+            }
+
+            let sleep = this.sleep.get_or_insert_with(|| Box::pin(this.timer.sleep(this.delay)));
+            if sleep.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+
+            this.attempts.push((this.factory)());
+            this.launched += 1;
+            this.sleep = None;
+            #[cfg(feature = "std")]
+            crate::metrics::increment(crate::metrics::RETRIES, &[("combinator", "hedge"), ("reason", "delay")]);
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+#[derive(Default)]
+struct SharedWakers {
+    slots: Vec<Option<Waker>>,
+    free: Vec<usize>,
+}
+
+#[cfg(feature = "std")]
+/// Wakes every clone of a [`Shared`] future when the underlying future is woken.
+#[derive(Default)]
+struct SharedNotifier {
+    wakers: Mutex<SharedWakers>,
+}
+
+#[cfg(feature = "std")]
+impl SharedNotifier {
+    fn insert(&self) -> usize {
+        let mut wakers = self.wakers.lock().unwrap();
+        match wakers.free.pop() {
+            Some(slot) => slot,
+            None => {
+                wakers.slots.push(None);
+                wakers.slots.len() - 1
+            }
+        }
+    }
+
+    fn remove(&self, slot: usize) {
+        let mut wakers = self.wakers.lock().unwrap();
+        wakers.slots[slot] = None;
+        wakers.free.push(slot);
+    }
+
+    fn register(&self, slot: usize, waker: &Waker) {
+        let mut wakers = self.wakers.lock().unwrap();
+        match &mut wakers.slots[slot] {
+            Some(existing) if existing.will_wake(waker) => {}
+            entry => *entry = Some(waker.clone()),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::task::Wake for SharedNotifier {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        let woken: Vec<Waker> = {
+            let mut wakers = self.wakers.lock().unwrap();
+            wakers.slots.iter_mut().filter_map(Option::take).collect()
+        };
+
+        for waker in woken {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+struct SharedInner<F>
+where
+    F: Future,
+{
+    future: Mutex<Option<Pin<Box<F>>>>,
+    output: std::sync::OnceLock<F::Output>,
+    notifier: Arc<SharedNotifier>,
+}
+
+#[cfg(feature = "std")]
+/// A future that can be cloned, every clone resolving to a clone of the same output.
+/// The underlying future runs once, driven by whichever clone is polled.
+pub struct Shared<F>
+where
+    F: Future,
+{
+    inner: Arc<SharedInner<F>>,
+    slot: usize,
+}
+
+#[cfg(feature = "std")]
+pub fn shared<F>(future: F) -> Shared<F>
+where
+    F: Future,
+    F::Output: Clone,
+{
+    let notifier = Arc::new(SharedNotifier::default());
+    let slot = notifier.insert();
+
+    Shared {
+        inner: Arc::new(SharedInner {
+            future: Mutex::new(Some(Box::pin(future))),
+            output: std::sync::OnceLock::new(),
+            notifier,
+        }),
+        slot,
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F> Shared<F>
+where
+    F: Future,
+    F::Output: Clone,
+{
+    /// The output, if the underlying future has already completed.
+    pub fn peek(&self) -> Option<&F::Output> {
+        self.inner.output.get()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F> Clone for Shared<F>
+where
+    F: Future,
+{
+    fn clone(&self) -> Self {
+        Shared {
+            inner: self.inner.clone(),
+            slot: self.inner.notifier.insert(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F> Drop for Shared<F>
+where
+    F: Future,
+{
+    fn drop(&mut self) {
+        self.inner.notifier.remove(self.slot);
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F> Future for Shared<F>
+where
+    F: Future,
+    F::Output: Clone,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        profiled(Kind::Shared, || {
+            let inner = &self.inner;
+            if let Some(output) = inner.output.get() {
+                return Poll::Ready(output.clone());
+            }
+
+            // Registered before trying to poll, so a clone that is polling the future right now
+            // is guaranteed to wake us once it makes progress
+            inner.notifier.register(self.slot, cx.waker());
+
+            let mut future = match inner.future.try_lock() {
+                Ok(future) => future,
+                Err(std::sync::TryLockError::WouldBlock) => return Poll::Pending,
+                Err(std::sync::TryLockError::Poisoned(_)) => panic!("Shared future panicked while being polled"),
+            };
+
+            // Another clone may have completed the future between the check above and taking the lock
+            let Some(fut) = future.as_mut() else {
+                drop(future);
+                return Poll::Ready(inner.output.get().unwrap().clone());
+            };
+
+            let waker = Waker::from(inner.notifier.clone());
+            match fut.as_mut().poll(&mut Context::from_waker(&waker)) {
+                Poll::Ready(output) => {
+                    *future = None;
+                    let _ = inner.output.set(output);
+                    drop(future);
+
+                    std::task::Wake::wake_by_ref(&inner.notifier);
+                    Poll::Ready(inner.output.get().unwrap().clone())
+                }
+                Poll::Pending => Poll::Pending,
+            }
+        })
+    }
+}
+
+/// Types with an associative way of merging two values, e.g. the errors merged by [`validate`].
+pub trait Semigroup {
+    fn combine(&self, other: &Self) -> Self;
+}
+
+/// A [`Semigroup`] with an identity element, as used by [`combine`] and [`fut_id`].
+///
+/// `combine` used to be a method of `Monoid` itself. It now lives on [`Semigroup`] so that
+/// types without an identity, such as [`NonEmptyVec`], can still be merged. An existing
+/// implementation moves its `combine` into a `Semigroup` impl and keeps `identity` here:
+///
+/// ```
+/// use combinators::comb::{Monoid, Semigroup};
+///
+/// struct Total(u32);
+///
+/// impl Semigroup for Total {
+///     fn combine(&self, other: &Self) -> Self {
+///         Total(self.0 + other.0)
+///     }
+/// }
+///
+/// impl Monoid for Total {
+///     fn identity() -> Self {
+///         Total(0)
+///     }
+/// }
+/// ```
+///
+/// Callers are unaffected: `value.combine(&other)` resolves through the supertrait as before.
+pub trait Monoid: Semigroup {
+    fn identity() -> Self;
+}
+
+#[cfg(feature = "alloc")]
+impl<T: Clone> Semigroup for Vec<T> {
+    fn combine(&self, other: &Self) -> Self {
+        let mut combined = self.clone();
+        combined.extend_from_slice(other);
+        combined
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: Clone> Monoid for Vec<T> {
+    fn identity() -> Self {
+        Vec::new()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Semigroup for String {
+    fn combine(&self, other: &Self) -> Self {
+        let mut combined = self.clone();
+        combined.push_str(other);
+        combined
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Monoid for String {
+    fn identity() -> Self {
+        String::new()
+    }
+}
+
+#[cfg(feature = "alloc")]
+/// A vector with at least one element. Used to report errors, where an empty
+/// collection of errors would mean there was no failure in the first place.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "derive", derive(serde::Serialize, serde::Deserialize))]
+pub struct NonEmptyVec<T> {
+    head: T,
+    tail: Vec<T>,
+}
+
+#[cfg(feature = "alloc")]
+impl<T> NonEmptyVec<T> {
+    pub fn new(head: T) -> Self {
+        NonEmptyVec {
+            head,
+            tail: Vec::new(),
+        }
+    }
+
+    pub fn from_vec(vec: Vec<T>) -> Option<Self> {
+        let mut iter = vec.into_iter();
+        let head = iter.next()?;
+        Some(NonEmptyVec {
+            head,
+            tail: iter.collect(),
+        })
+    }
+
+    pub fn push(&mut self, value: T) {
+        self.tail.push(value);
+    }
+
+    pub fn first(&self) -> &T {
+        &self.head
+    }
+
+    pub fn len(&self) -> usize {
+        self.tail.len() + 1
+    }
+
+    /// Always `false`, there is at least one element.
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        core::iter::once(&self.head).chain(self.tail.iter())
+    }
+
+    pub fn into_vec(self) -> Vec<T> {
+        let mut vec = Vec::with_capacity(self.len());
+        vec.push(self.head);
+        vec.extend(self.tail);
+        vec
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: Clone> Semigroup for NonEmptyVec<T> {
+    fn combine(&self, other: &Self) -> Self {
+        let mut combined = self.clone();
+        combined.tail.extend(other.iter().cloned());
+        combined
+    }
+}
+
+pub type Validate<A, B, AR, BR, E> =
+    CombineWith<A, B, Result<AR, E>, Result<BR, E>, fn(Result<AR, E>, Result<BR, E>) -> Result<(AR, BR), E>, Result<(AR, BR), E>>;
+
+/// Like [`try_join`], but both futures always run to completion and, if both fail,
+/// their errors are merged through the [`Semigroup`] instance.
+pub fn validate<A, B, AR, BR, E>(a: A, b: B) -> Validate<A, B, AR, BR, E>
+where
+    A: Future<Output = Result<AR, E>>,
+    B: Future<Output = Result<BR, E>>,
+    E: Semigroup,
+{
+    combine_with(a, b, merge_validated as fn(_, _) -> _)
+}
+
+fn merge_validated<AR, BR, E: Semigroup>(a: Result<AR, E>, b: Result<BR, E>) -> Result<(AR, BR), E> {
+    match (a, b) {
+        (Ok(a), Ok(b)) => Ok((a, b)),
+        (Err(e), Ok(_)) | (Ok(_), Err(e)) => Err(e),
+        (Err(e1), Err(e2)) => Err(e1.combine(&e2)),
+    }
+}
+
+#[cfg(feature = "alloc")]
+pub struct ValidateAll<F>
+where
+    F: Future,
+{
+    join: JoinAll<F>,
+}
+
+#[cfg(feature = "alloc")]
+/// Runs every future to completion and returns either all of the values or every error
+/// that occurred, in the order the futures were given.
+pub fn validate_all<I, T, E>(iter: I) -> ValidateAll<I::Item>
+where
+    I: IntoIterator,
+    I::Item: Future<Output = Result<T, E>>,
+{
+    ValidateAll { join: join_all(iter) }
+}
+
+#[cfg(feature = "alloc")]
+impl<F> ValidateAll<F>
+where
+    F: Future,
+{
+    /// See [`JoinAll::policy`].
+    pub fn policy(mut self, policy: PollPolicy) -> Self {
+        self.join = self.join.policy(policy);
+        self
+    }
+
+    /// See [`JoinAll::budget`].
+    pub fn budget(mut self, polls: usize) -> Self {
+        self.join = self.join.budget(polls);
+        self
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<F, T, E> Future for ValidateAll<F>
+where
+    F: Future<Output = Result<T, E>>,
+{
+    type Output = Result<Vec<T>, NonEmptyVec<E>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let join = unsafe { self.map_unchecked_mut(|this| &mut this.join) };
+        join.poll(cx).map(collect_validated)
+    }
+}
+
+#[cfg(feature = "alloc")]
+fn collect_validated<T, E>(results: Vec<Result<T, E>>) -> Result<Vec<T>, NonEmptyVec<E>> {
+    let mut values = Vec::with_capacity(results.len());
+    let mut errors: Option<NonEmptyVec<E>> = None;
+
+    for result in results {
+        match (result, &mut errors) {
+            (Ok(value), _) => values.push(value),
+            (Err(e), Some(errors)) => errors.push(e),
+            (Err(e), None) => errors = Some(NonEmptyVec::new(e)),
+        }
+    }
+
+    match errors {
+        Some(errors) => Err(errors),
+        None => Ok(values),
+    }
+}
+
+pub fn fut_id<T: Monoid>() -> Ready<T> {
+    ready(T::identity())
+}
+
+
+/// A future that is immediately ready with the given value.
+#[derive(Debug)]
+pub struct Ready<T>(Option<T>);
+
+impl<T> Unpin for Ready<T> {}
+
+pub fn ready<T>(value: T) -> Ready<T> {
+    Ready(Some(value))
+}
+
+impl<T> Future for Ready<T> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Poll::Ready(self.0.take().expect("Ready polled after completion"))
+    }
+}
+
+/// Lifts a value into a future, the `pure`/`return` of the future applicative.
+pub fn pure<T>(value: T) -> Ready<T> {
+    ready(value)
+}
+
+/// Futures can be mapped over, backed by [`map`].
+pub trait Functor: Future + Sized {
+    fn fmap<U, M>(self, mapper: M) -> Mapping<Self, Self::Output, M, U>
+    where
+        M: Fn(Self::Output) -> U;
+}
+
+impl<F: Future> Functor for F {
+    fn fmap<U, M>(self, mapper: M) -> Mapping<Self, Self::Output, M, U>
+    where
+        M: Fn(Self::Output) -> U,
+    {
+        map(self, mapper)
+    }
+}
+
+/// Futures can be run side by side and their results applied, backed by [`combine_with`].
+pub trait Applicative: Functor {
+    fn pure(value: Self::Output) -> Ready<Self::Output> {
+        pure(value)
+    }
+
+    /// Applies the function produced by `ff` to the output of `self`.
+    /// Both futures are polled concurrently.
+    fn ap<G, U>(self, ff: G) -> Ap<G, Self, U>
+    where
+        G: Future,
+        G::Output: FnOnce(Self::Output) -> U;
+
+    fn lift_a2<B, M, U>(self, other: B, f: M) -> CombineWith<Self, B, Self::Output, B::Output, M, U>
+    where
+        B: Future,
+        M: FnOnce(Self::Output, B::Output) -> U;
+}
+
+/// Future returned by [`Applicative::ap`].
+pub type Ap<G, F, U> = CombineWith<
+    G,
+    F,
+    <G as Future>::Output,
+    <F as Future>::Output,
+    fn(<G as Future>::Output, <F as Future>::Output) -> U,
+    U,
+>;
+
+fn apply<A, U, M: FnOnce(A) -> U>(f: M, a: A) -> U {
+    f(a)
+}
+
+impl<F: Future> Applicative for F {
+    fn ap<G, U>(self, ff: G) -> Ap<G, Self, U>
+    where
+        G: Future,
+        G::Output: FnOnce(Self::Output) -> U,
+    {
+        combine_with(ff, self, apply as fn(_, _) -> _)
+    }
+
+    fn lift_a2<B, M, U>(self, other: B, f: M) -> CombineWith<Self, B, Self::Output, B::Output, M, U>
+    where
+        B: Future,
+        M: FnOnce(Self::Output, B::Output) -> U,
+    {
+        combine_with(self, other, f)
+    }
+}
+
+/// Futures can be chained, the continuation receiving the previous output. Backed by [`sequential`].
+pub trait Monad: Applicative {
+    fn bind<B, M>(self, f: M) -> Sequential<Self, B, M>
+    where
+        B: Future,
+        M: FnOnce(Self::Output) -> B;
+}
+
+impl<F: Future> Monad for F {
+    fn bind<B, M>(self, f: M) -> Sequential<Self, B, M>
+    where
+        B: Future,
+        M: FnOnce(Self::Output) -> B,
+    {
+        sequential(self, f)
+    }
+}
+
+
+enum MonoidCombineState<F1, F2>
+where
+    F1: Future,
+    F2: Future<Output = F1::Output>,
+{
+    Awaiting {
+        future1: SimpleState<F1, F1::Output>,
+        future2: SimpleState<F2, F1::Output>,
+    },
+    Completed,
+}
+
+pub struct MonoidCombine<F1, F2>
+where
+    F1: Future,
+    F2: Future<Output = F1::Output>,
+{
+    state: MonoidCombineState<F1, F2>,
+    order: PollOrder,
+    clock: BranchClock,
+}
+
+impl<F1, F2> MonoidCombine<F1, F2>
+where
+    F1: Future,
+    F2: Future<Output = F1::Output>,
+{
+    pub fn new(future1: F1, future2: F2) -> Self {
+        MonoidCombine {
+            state: MonoidCombineState::Awaiting {
+                future1: SimpleState::Future(future1),
+                future2: SimpleState::Future(future2),
+            },
+            order: PollOrder::default(),
+            clock: BranchClock::default(),
+        }
+    }
+
+    /// Sets the order the two futures are polled in.
+    pub fn policy(mut self, policy: PollPolicy) -> Self {
+        self.order = PollOrder::new(policy);
+        self
+    }
+}
+
+/// Polls both futures concurrently and combines their outputs with [`Semigroup::combine`].
+pub fn combine<F1, F2>(future1: F1, future2: F2) -> MonoidCombine<F1, F2>
+where
+    F1: Future,
+    F2: Future<Output = F1::Output>,
+    F1::Output: Monoid,
+{
+    MonoidCombine::new(future1, future2)
+}
+
+/// [`combine`] behind a box, so that combining an arbitrary number of futures in a loop
+/// keeps a single type.
+#[cfg(feature = "alloc")]
+pub fn combine_boxed<'a, F1, F2>(future1: F1, future2: F2) -> BoxFuture<'a, F1::Output>
+where
+    F1: Future + Send + 'a,
+    F2: Future<Output = F1::Output> + Send + 'a,
+    F1::Output: Monoid + Send,
+{
+    Box::pin(combine(future1, future2))
+}
+
+/// [`combine_boxed`] for futures that are not `Send`.
+#[cfg(feature = "alloc")]
+pub fn combine_boxed_local<'a, F1, F2>(future1: F1, future2: F2) -> LocalBoxFuture<'a, F1::Output>
+where
+    F1: Future + 'a,
+    F2: Future<Output = F1::Output> + 'a,
+    F1::Output: Monoid,
+{
+    Box::pin(combine(future1, future2))
+}
+
+impl<F1, F2> Future for MonoidCombine<F1, F2>
+where
+    F1: Future,
+    F2: Future<Output = F1::Output>, // Ensure F2's Output is the same as F1's Output
+    F1::Output: Monoid,
+{
+    type Output = F1::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        profiled(Kind::Combine, || {
+            let MonoidCombine { state: this, order, clock } = unsafe { self.get_unchecked_mut() };
+            let (future1, future2) = match this {
+                MonoidCombineState::Awaiting { future1, future2 } => (future1, future2),
+                MonoidCombineState::Completed => panic!("MonoidCombine polled after completion"),
+            };
+            clock.start();
+            let clock = &*clock;
+
+            order.both(
+                cx,
+                |cx| {
+                    if future1.poll_in_place(cx) {
+                        clock.completed(Kind::Combine, 0, true);
+                    }
+                },
+                |cx| {
+                    if future2.poll_in_place(cx) {
+                        clock.completed(Kind::Combine, 1, true);
+                    }
+                },
+            );
+
+            match (future1, future2) {
+                (SimpleState::Ok(_), SimpleState::Ok(_)) => match core::mem::replace(this, MonoidCombineState::Completed) {
+                    MonoidCombineState::Awaiting {
+                        future1: SimpleState::Ok(res1),
+                        future2: SimpleState::Ok(res2),
+                    } => Poll::Ready(res1.combine(&res2)),
+                    _ => unreachable!(),
+                },
+                _ => Poll::Pending,
+            }
+        })
+    }
+}
+
+
+#[cfg(feature = "tracing")]
+/// A future running inside its own `tracing` span, created with [`Traceable::traced`].
+///
+/// The span is opened on the first poll, so it nests under the span of the combinator
+/// polling it, and is entered on every poll. Completion is reported as an event carrying
+/// the poll count and the time since the first poll; being dropped before completing is
+/// reported too.
+pub struct Traced<F> {
+    inner: F,
+    label: &'static str,
+    span: Option<tracing::Span>,
+    started: Option<std::time::Instant>,
+    polls: u64,
+    done: bool,
+}
+
+#[cfg(feature = "tracing")]
+pub trait Traceable: Future + Sized {
+    /// Wraps the future in a span named after `label`, see [`Traced`].
+    fn traced(self, label: &'static str) -> Traced<Self> {
+        Traced {
+            inner: self,
+            label,
+            span: None,
+            started: None,
+            polls: 0,
+            done: false,
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl<F: Future> Traceable for F {}
+
+#[cfg(feature = "tracing")]
+impl<F> Future for Traced<F>
+where
+    F: Future,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let label = this.label;
+        let span = this.span.get_or_insert_with(|| {
+            tracing::info_span!(
+                "combinator",
+                label,
+                polls = tracing::field::Empty,
+                elapsed_ms = tracing::field::Empty
+            )
+        });
+        let started = *this.started.get_or_insert_with(std::time::Instant::now);
+
+        let _entered = span.enter();
+        this.polls += 1;
+
+        let inner = unsafe { Pin::new_unchecked(&mut this.inner) };
+        let res = crate::profile::timed(label, crate::profile::global_threshold(), || inner.poll(cx));
+        if res.is_ready() {
+            this.done = true;
+            let elapsed = started.elapsed();
+            span.record("polls", this.polls);
+            span.record("elapsed_ms", elapsed.as_secs_f64() * 1000.0);
+            info!(polls = this.polls, ?elapsed, "{label} completed");
+        }
+        res
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl<F> Drop for Traced<F> {
+    fn drop(&mut self) {
+        if let (Some(span), false) = (&self.span, self.done) {
+            info!(parent: span, polls = self.polls, "{} dropped before completion", self.label);
+        }
+    }
+}
+
+/// What a node of a combinator tree does, see [`Describe`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Join,
+    TryJoin,
+    JoinAll,
+    Sequence,
+    Map,
+    Combine,
+    Select,
+    Race,
+    CatchUnwind,
+    Abortable,
+    Shared,
+    Ready,
+    /// A future that isn't a combinator, named with [`leaf`].
+    Leaf,
+    /// A task running on the tokio runtime, whose future can't be inspected any more.
+    Spawned,
+    /// A scope running its body and waiting for the tasks spawned in it.
+    Scope,
+}
+
+impl Kind {
+    /// Snake-case name of the combinator, as used in trees and in events.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Kind::Join => "join",
+            Kind::TryJoin => "try_join",
+            Kind::JoinAll => "join_all",
+            Kind::Sequence => "sequence",
+            Kind::Map => "map",
+            Kind::Combine => "combine",
+            Kind::Select => "select",
+            Kind::Race => "race",
+            Kind::CatchUnwind => "catch_unwind",
+            Kind::Abortable => "abortable",
+            Kind::Shared => "shared",
+            Kind::Ready => "ready",
+            Kind::Leaf => "leaf",
+            Kind::Spawned => "spawned",
+            Kind::Scope => "scope",
+        }
+    }
+}
+
+impl core::fmt::Display for Kind {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Static view of a combinator tree, so a pipeline can be inspected before it runs,
+/// see [`describe_tree`] and [`describe_dot`].
+///
+/// Implemented by the combinators whenever their children implement it too. Plain futures
+/// (`async` blocks and functions) are made describable with [`leaf`].
+pub trait Describe {
+    fn kind(&self) -> Kind;
+
+    /// Name given to the node, e.g. by [`leaf`] or `traced`.
+    fn label(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Calls `visit` with every child that is still running. Children that already completed,
+    /// and the continuation of a [`sequential`] that hasn't started yet, are not visited.
+    fn for_each_child(&self, _visit: &mut dyn FnMut(&dyn Describe)) {}
+}
+
+impl<F, T> SimpleState<F, T>
+where
+    F: Future<Output = T> + Describe,
+{
+    fn describe_pending(&self, visit: &mut dyn FnMut(&dyn Describe)) {
+        if let SimpleState::Future(fut) = self {
+            visit(fut);
+        }
+    }
+}
+
+/// A plain future given a name, so it shows up in [`Describe`] trees.
+pub struct Leaf<F> {
+    future: F,
+    label: &'static str,
+}
+
+pub fn leaf<F: Future>(future: F, label: &'static str) -> Leaf<F> {
+    Leaf { future, label }
+}
+
+impl<F: Future> Future for Leaf<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+        #[cfg(feature = "std")]
+        return crate::profile::timed(this.label, crate::profile::global_threshold(), || future.poll(cx));
+        #[cfg(not(feature = "std"))]
+        future.poll(cx)
+    }
+}
+
+impl<F> Describe for Leaf<F> {
+    fn kind(&self) -> Kind {
+        Kind::Leaf
+    }
+
+    fn label(&self) -> Option<&'static str> {
+        Some(self.label)
+    }
+}
+
+impl<T> Describe for Ready<T> {
+    fn kind(&self) -> Kind {
+        Kind::Ready
+    }
+}
+
+impl<A, B, AR, BR, E> Describe for TryJoin<A, B, AR, BR, E>
+where
+    A: Future<Output = Result<AR, E>> + Describe,
+    B: Future<Output = Result<BR, E>> + Describe,
+{
+    fn kind(&self) -> Kind {
+        Kind::TryJoin
+    }
+
+    fn for_each_child(&self, visit: &mut dyn FnMut(&dyn Describe)) {
+        if let TryJoinState::Polling { a, b } = &self.state {
+            if let State::Future(a) = a {
+                visit(a);
+            }
+            if let State::Future(b) = b {
+                visit(b);
+            }
+        }
+    }
+}
+
+impl<A, B, AR, BR> Describe for JoinFutures<A, B, AR, BR>
+where
+    A: Future<Output = AR> + Describe,
+    B: Future<Output = BR> + Describe,
+{
+    fn kind(&self) -> Kind {
+        Kind::Join
+    }
+
+    fn for_each_child(&self, visit: &mut dyn FnMut(&dyn Describe)) {
+        if let JoinFuturesState::Polling { a, b } = &self.state {
+            a.describe_pending(visit);
+            b.describe_pending(visit);
         }
+    }
+}
 
+impl<A, B, AR, BR, AR2, BR2, F, G> Describe for JoinFuturesBMap<A, B, AR, BR, AR2, BR2, F, G>
+where
+    A: Future<Output = AR> + Describe,
+    B: Future<Output = BR> + Describe,
+    F: FnOnce(AR) -> AR2,
+    G: FnOnce(BR) -> BR2,
+{
+    fn kind(&self) -> Kind {
+        Kind::Join
+    }
 
-        match (a, b) {
-            (SimpleState::Ok(_), SimpleState::Ok(_)) => match std::mem::replace(this, Self::Done) {
-                CombineWith::Polling {
-                    a: SimpleState::Ok(a),
-                    b: SimpleState::Ok(b),
-                    combine,
-                } => {
-                    // println!("RDY");
-                    Poll::Ready(combine(a, b))
-                },
-                _ => unreachable!(),
-            },
-            _ =>{ 
-                // println!("pending");
-                Poll::Pending
-            },
+    fn for_each_child(&self, visit: &mut dyn FnMut(&dyn Describe)) {
+        if let JoinFuturesBMapState::Polling { a, b, .. } = &self.state {
+            a.describe_pending(visit);
+            b.describe_pending(visit);
         }
     }
 }
 
-pub trait Monoid {
-    fn identity() -> Self;
-    fn combine(&self, other: &Self) -> Self;
+impl<A, B, M> Describe for Sequential<A, B, M>
+where
+    A: Future + Describe,
+    B: Future + Describe,
+    M: FnOnce(A::Output) -> B,
+{
+    fn kind(&self) -> Kind {
+        Kind::Sequence
+    }
+
+    fn for_each_child(&self, visit: &mut dyn FnMut(&dyn Describe)) {
+        match &self.state {
+            SequentialState::First { first, .. } => visit(first),
+            SequentialState::Second(second) => visit(second),
+            SequentialState::Done => {}
+        }
+    }
 }
 
-pub fn fut_id<T: Monoid>() -> Pin<Box<dyn Future<Output = T>>> {
-    Box::pin(async move { T::identity() })
+impl<F, G, T, U> Describe for Sequence<F, G, T, U>
+where
+    F: Future<Output = T> + Describe,
+    G: Future<Output = U> + Describe,
+{
+    fn kind(&self) -> Kind {
+        Kind::Sequence
+    }
+
+    fn for_each_child(&self, visit: &mut dyn FnMut(&dyn Describe)) {
+        if let SequenceState::Polling { first, second } = &self.state {
+            first.describe_pending(visit);
+            second.describe_pending(visit);
+        }
+    }
 }
 
-// pub fn fut_id<T: Monoid>() -> impl Future<Output = T> {
-//     async move { T::identity() }
-// }
+impl<F, T, M, U> Describe for Mapping<F, T, M, U>
+where
+    F: Future<Output = T> + Describe,
+    M: Fn(T) -> U,
+{
+    fn kind(&self) -> Kind {
+        Kind::Map
+    }
 
+    fn for_each_child(&self, visit: &mut dyn FnMut(&dyn Describe)) {
+        if let MappingState::Polling { future, .. } = &self.state {
+            visit(future);
+        }
+    }
+}
 
-enum MonoidCombineState<F1, F2>
+impl<A, B, AR, BR, M, MR> Describe for CombineWith<A, B, AR, BR, M, MR>
 where
-    F1: Future,
-    F2: Future,
+    A: Future<Output = AR> + Describe,
+    B: Future<Output = BR> + Describe,
+    M: FnOnce(AR, BR) -> MR,
 {
-    Awaiting {
-        future1: F1,
-        future2: F2,
-    },
-    Completed,
+    fn kind(&self) -> Kind {
+        Kind::Combine
+    }
+
+    fn for_each_child(&self, visit: &mut dyn FnMut(&dyn Describe)) {
+        if let CombineWithState::Polling { a, b, .. } = &self.state {
+            a.describe_pending(visit);
+            b.describe_pending(visit);
+        }
+    }
 }
 
-pub struct MonoidCombine<F1, F2>
+impl<F1, F2> Describe for MonoidCombine<F1, F2>
 where
-    F1: Future,
-    F2: Future,
+    F1: Future + Describe,
+    F2: Future<Output = F1::Output> + Describe,
 {
-    state: MonoidCombineState<F1, F2>,
+    fn kind(&self) -> Kind {
+        Kind::Combine
+    }
+
+    fn for_each_child(&self, visit: &mut dyn FnMut(&dyn Describe)) {
+        if let MonoidCombineState::Awaiting { future1, future2 } = &self.state {
+            future1.describe_pending(visit);
+            future2.describe_pending(visit);
+        }
+    }
 }
 
-impl<F1, F2> MonoidCombine<F1, F2>
+impl<A, B> Describe for Select<A, B>
 where
-    F1: Future,
-    F2: Future,
+    A: Future + Describe,
+    B: Future + Describe,
 {
-    pub fn new(future1: F1, future2: F2) -> Self {
-        MonoidCombine {
-            state: MonoidCombineState::Awaiting { future1, future2 },
+    fn kind(&self) -> Kind {
+        Kind::Select
+    }
+
+    fn for_each_child(&self, visit: &mut dyn FnMut(&dyn Describe)) {
+        if !self.done {
+            visit(&self.a);
+            visit(&self.b);
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<F> Describe for JoinAll<F>
+where
+    F: Future + Describe,
+{
+    fn kind(&self) -> Kind {
+        Kind::JoinAll
+    }
+
+    fn for_each_child(&self, visit: &mut dyn FnMut(&dyn Describe)) {
+        for elem in self.elems.iter() {
+            elem.describe_pending(visit);
         }
     }
 }
 
-pub fn combine<F1, F2>(future1: F1, future2: F2) -> Pin<Box<dyn Future<Output = F1::Output> + 'static>>
+#[cfg(feature = "alloc")]
+impl<F> Describe for ValidateAll<F>
 where
-    F1: Future + Unpin + 'static, //Add static lifetime for now
-    F2: Future<Output = F1::Output> + Unpin + 'static, 
-    F1::Output: Monoid, //Output needs to be Monoid
+    F: Future + Describe,
 {
-    Box::pin(MonoidCombine::new(future1, future2))
+    fn kind(&self) -> Kind {
+        Kind::JoinAll
+    }
+
+    fn for_each_child(&self, visit: &mut dyn FnMut(&dyn Describe)) {
+        self.join.for_each_child(visit)
+    }
 }
 
+#[cfg(feature = "std")]
+impl<F> Describe for JoinSettledCatching<F>
+where
+    F: Future + Describe,
+{
+    fn kind(&self) -> Kind {
+        Kind::JoinAll
+    }
 
-impl<F1, F2> Future for MonoidCombine<F1, F2>
+    fn for_each_child(&self, visit: &mut dyn FnMut(&dyn Describe)) {
+        self.join.for_each_child(visit)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<F> Describe for RaceOk<F>
 where
-    F1: Future + Unpin,
-    F2: Future<Output = F1::Output> + Unpin, // Ensure F2's Output is the same as F1's Output
-    F1::Output: Monoid,
+    F: Describe,
 {
-    type Output = F1::Output;
+    fn kind(&self) -> Kind {
+        Kind::Race
+    }
 
-    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        let this = self.as_mut().get_mut();
+    fn for_each_child(&self, visit: &mut dyn FnMut(&dyn Describe)) {
+        for future in &self.futures {
+            visit(&**future);
+        }
+    }
+}
 
-        match &mut this.state {
-            MonoidCombineState::Awaiting { future1, future2 } => {
-                let mut f1_ready = None;
-                let mut f2_ready = None;
+#[cfg(feature = "alloc")]
+impl<Tm, Fac, F> Describe for Hedge<Tm, Fac, F>
+where
+    Tm: Timer,
+    F: Describe,
+{
+    fn kind(&self) -> Kind {
+        Kind::Race
+    }
 
-                if let Poll::Ready(res) = Pin::new(future1).poll(cx) {
-                    f1_ready = Some(res);
-                }
+    fn for_each_child(&self, visit: &mut dyn FnMut(&dyn Describe)) {
+        self.attempts.for_each_child(visit)
+    }
+}
 
-                if let Poll::Ready(res) = Pin::new(future2).poll(cx) {
-                    f2_ready = Some(res);
-                }
+#[cfg(feature = "std")]
+impl<F> Describe for CatchUnwind<F>
+where
+    F: Future + Describe,
+{
+    fn kind(&self) -> Kind {
+        Kind::CatchUnwind
+    }
 
-                match (f1_ready, f2_ready) {
-                    (Some(res1), Some(res2)) => {
-                        this.state = MonoidCombineState::Completed;
-                        Poll::Ready(res1.combine(&res2))
-                    },
-                    _ => Poll::Pending,
-                }
-            }
-            MonoidCombineState::Completed => {
-                panic!("MonoidCombine polled after completion")
+    fn for_each_child(&self, visit: &mut dyn FnMut(&dyn Describe)) {
+        visit(&self.future);
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F> Describe for Abortable<F>
+where
+    F: Future + Describe,
+{
+    fn kind(&self) -> Kind {
+        Kind::Abortable
+    }
+
+    fn for_each_child(&self, visit: &mut dyn FnMut(&dyn Describe)) {
+        if let AbortableState::Polling(future) = &self.state {
+            visit(future);
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F> Describe for Shared<F>
+where
+    F: Future + Describe,
+{
+    fn kind(&self) -> Kind {
+        Kind::Shared
+    }
+
+    fn for_each_child(&self, visit: &mut dyn FnMut(&dyn Describe)) {
+        // Skipped while another clone is polling it
+        if let Ok(future) = self.inner.future.try_lock() {
+            if let Some(future) = future.as_ref() {
+                visit(&**future);
             }
         }
     }
 }
 
+#[cfg(feature = "tracing")]
+impl<F> Describe for Traced<F>
+where
+    F: Describe,
+{
+    fn kind(&self) -> Kind {
+        self.inner.kind()
+    }
+
+    fn label(&self) -> Option<&'static str> {
+        Some(self.label)
+    }
+
+    fn for_each_child(&self, visit: &mut dyn FnMut(&dyn Describe)) {
+        self.inner.for_each_child(visit)
+    }
+}
+
+#[cfg(feature = "alloc")]
+fn node_name(node: &dyn Describe) -> String {
+    match node.label() {
+        Some(label) => alloc::format!("{} {label:?}", node.kind()),
+        None => alloc::format!("{}", node.kind()),
+    }
+}
+
+#[cfg(feature = "alloc")]
+/// Renders the tree as indented text, one node per line.
+pub fn describe_tree(root: &dyn Describe) -> String {
+    fn visit(node: &dyn Describe, depth: usize, out: &mut String) {
+        for _ in 0..depth {
+            out.push_str("  ");
+        }
+        out.push_str(&node_name(node));
+        out.push('\n');
+        node.for_each_child(&mut |child| visit(child, depth + 1, out));
+    }
+
+    let mut out = String::new();
+    visit(root, 0, &mut out);
+    out
+}
+
+#[cfg(feature = "alloc")]
+/// Renders the tree as a Graphviz DOT digraph.
+pub fn describe_dot(root: &dyn Describe) -> String {
+    use core::fmt::Write;
+
+    fn visit(node: &dyn Describe, next_id: &mut usize, out: &mut String) -> usize {
+        let id = *next_id;
+        *next_id += 1;
+
+        let shape = if node.kind() == Kind::Leaf { "box" } else { "ellipse" };
+        let name = node_name(node).replace('\\', "\\\\").replace('"', "\\\"");
+        writeln!(out, "    n{id} [label=\"{name}\", shape={shape}];").unwrap();
+
+        node.for_each_child(&mut |child| {
+            let child_id = visit(child, next_id, out);
+            writeln!(out, "    n{id} -> n{child_id};").unwrap();
+        });
+        id
+    }
+
+    let mut out = String::from("digraph combinators {\n");
+    visit(root, &mut 0, &mut out);
+    out.push_str("}\n");
+    out
+}
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use crate::comb::*;
+    use crate::executor::{block_on, yield_now};
+
+    #[test]
+    fn identity_law() {
+        block_on(async {
+            let result1 = identity(async move { 0 }.await);
+            let result2 = sequential(async move { 0 }, |x| async move { identity(x) }).await;
+
+            assert_eq!(result1, result2);
+        })
+    }
+
+    #[test]
+    fn associativity() {
+        block_on(async {
+            let a = async { 1 };
+            let b = |x: i32| async move { x + 1 };
+            let c = |x: i32| async move { x * 2 };
+
+            // Applying sequential(sequential(A, B), C)
+            let res1 = sequential(sequential(a, b), c).await;
+
+            // Reset a + to avoid using moved values
+            let a = async { 1 };
+
+            // Applying sequential(A, sequential(B, C))
+            let res2 = sequential(a, move |x| sequential(b(x), c)).await;
+
+            assert_eq!(res1, res2, "Sequential composition should be associative");
+        })
+    }
+
+    #[test]
+    fn functor_composition() {
+        block_on(async {
+            let f = |x: i32| x + 1;
+            let g = |x: i32| x * 3;
+
+            let res1 = async { 2 }.fmap(move |x| g(f(x))).await;
+            let res2 = async { 2 }.fmap(f).fmap(g).await;
+
+            assert_eq!(res1, res2);
+        })
+    }
+
+    #[test]
+    fn applicative_ap_and_lift() {
+        block_on(async {
+            let applied = async { 20 }.ap(async { |x: i32| x + 1 }).await;
+            let lifted = pure(2).lift_a2(async { 3 }, |a, b| a * b).await;
+
+            assert_eq!(applied, 21);
+            assert_eq!(lifted, 6);
+        })
+    }
+
+    #[test]
+    fn bind_with_pending_continuation() {
+        block_on(async {
+            let res = async { 1 }
+                .bind(|x| async move {
+                    yield_now().await;
+                    x + 1
+                })
+                .bind(|x| async move {
+                    yield_now().await;
+                    x * 2
+                })
+                .await;
+
+            assert_eq!(res, 4);
+        })
+    }
+
+    #[test]
+    fn join_all_only_repolls_woken_branches() {
+        use std::cell::{Cell, RefCell};
+
+        let released = Cell::new(false);
+        let parked: RefCell<Option<Waker>> = RefCell::new(None);
+        let polls = Cell::new(0);
+
+        let waiting = std::future::poll_fn(|cx| {
+            polls.set(polls.get() + 1);
+            if released.get() {
+                return Poll::Ready(0);
+            }
+            *parked.borrow_mut() = Some(cx.waker().clone());
+            Poll::Pending
+        });
+
+        let busy = async {
+            for _ in 0..5 {
+                yield_now().await;
+            }
+            released.set(true);
+            parked.borrow_mut().take().unwrap().wake();
+            5
+        };
+
+        let res = block_on(join_all([
+            Box::pin(waiting) as Pin<Box<dyn Future<Output = i32> + '_>>,
+            Box::pin(busy),
+        ]));
+
+        assert_eq!(res, vec![0, 5]);
+        // Once to register, once after being released, never for the other branch's yields
+        assert_eq!(polls.get(), 2);
+    }
+
+    #[test]
+    fn join_all_yields_once_the_budget_is_spent() {
+        let mut join = join_all((0..1000).map(ready)).budget(100);
+
+        let mut polls = 0;
+        let res = block_on(std::future::poll_fn(|cx| {
+            polls += 1;
+            Pin::new(&mut join).poll(cx)
+        }));
+
+        assert_eq!(res, (0..1000).collect::<Vec<_>>());
+        assert_eq!(polls, 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "JoinAll polled after completion")]
+    fn join_all_panics_when_polled_after_completion() {
+        let mut join = join_all((0..3).map(ready));
+        let mut cx = Context::from_waker(Waker::noop());
+
+        assert_eq!(Pin::new(&mut join).poll(&mut cx), Poll::Ready(vec![0, 1, 2]));
+        let _ = Pin::new(&mut join).poll(&mut cx);
+    }
+
+    #[test]
+    fn poll_policy_controls_branch_order() {
+        use std::cell::RefCell;
+
+        let order = |policy| {
+            let log = RefCell::new(Vec::new());
+            let branches = (0..4).map(|i| {
+                let log = &log;
+                async move {
+                    log.borrow_mut().push(i);
+                    yield_now().await;
+                    log.borrow_mut().push(i);
+                }
+            });
+            block_on(join_all(branches).policy(policy));
+            log.into_inner()
+        };
+
+        assert_eq!(order(PollPolicy::Biased), vec![0, 1, 2, 3, 0, 1, 2, 3]);
+        assert_eq!(order(PollPolicy::RoundRobin), vec![0, 1, 2, 3, 1, 2, 3, 0]);
+        let random = PollPolicy::Random { seed: 42 };
+        assert_eq!(order(random), order(random));
+
+        // Both finish on their second poll, which round-robin starts from `b`
+        let tie = || select(yield_now(), yield_now());
+        assert!(matches!(block_on(tie()), Either::Left(())));
+        assert!(matches!(block_on(tie().policy(PollPolicy::RoundRobin)), Either::Right(())));
+
+        // The joins built on top of `join_all` and `combine_with` take a policy as well
+        let log = RefCell::new(Vec::new());
+        let check = |i| {
+            let log = &log;
+            async move {
+                log.borrow_mut().push(i);
+                yield_now().await;
+                log.borrow_mut().push(i);
+                Ok::<_, String>(i)
+            }
+        };
+        block_on(validate_all((0..3).map(check)).policy(PollPolicy::RoundRobin)).unwrap();
+        block_on(validate(check(0), check(1)).policy(PollPolicy::RoundRobin)).unwrap();
+        block_on(join_settled_catching((0..2).map(check)).policy(PollPolicy::RoundRobin));
+        assert_eq!(log.into_inner(), vec![0, 1, 2, 1, 2, 0, 0, 1, 1, 0, 0, 1, 1, 0]);
+    }
+
+    #[test]
+    fn describes_an_unrun_pipeline() {
+        let pipeline = map(
+            join_futures(
+                sequence(leaf(async {}, "apply code"), leaf(async { 14 }, "discount")),
+                try_join(leaf(async { Ok::<_, ()>(1) }, "price"), ready(Ok(2))),
+            ),
+            |(discount, prices)| prices.map(|(a, b)| (a + b) * discount),
+        );
+
+        assert_eq!(
+            describe_tree(&pipeline),
+            "map\n  join\n    sequence\n      leaf \"apply code\"\n      leaf \"discount\"\n    try_join\n      leaf \"price\"\n      ready\n"
+        );
+
+        let dot = describe_dot(&pipeline);
+        assert!(dot.starts_with("digraph combinators {"));
+        assert!(dot.contains("n0 -> n1;"));
+        assert!(dot.contains("[label=\"leaf \\\"price\\\"\", shape=box]"));
+
+        assert_eq!(block_on(pipeline), Ok(42));
+    }
+
+    #[test]
+    fn describes_derived_combinators() {
+        let stock = |label| leaf(async { Ok::<i32, String>(1) }, label);
+
+        let all = validate_all([stock("shop a"), stock("shop b")]);
+        assert_eq!(describe_tree(&all), "join_all\n  leaf \"shop a\"\n  leaf \"shop b\"\n");
+        let settled = join_settled_catching([stock("shop a")]);
+        assert_eq!(describe_tree(&settled), "join_all\n  catch_unwind\n    leaf \"shop a\"\n");
+
+        let checked = validate(
+            stock("code").fmap(|res| res.map(|x| x + 1)),
+            pure(3).lift_a2(stock("discount"), |a, b| b.map(|b| a * b)),
+        );
+        assert_eq!(
+            describe_tree(&checked),
+            "combine\n  map\n    leaf \"code\"\n  combine\n    ready\n    leaf \"discount\"\n"
+        );
+        assert_eq!(block_on(checked), Ok((2, 3)));
+
+        type BoxError = Box<dyn std::error::Error + Send + Sync>;
+        let priced = try_join_isolated(leaf(async { Ok::<_, BoxError>(1) }, "price"), ready(Ok(2))).bind(ready);
+        assert_eq!(
+            describe_tree(&priced),
+            "sequence\n  try_join\n    map\n      catch_unwind\n        leaf \"price\"\n    map\n      catch_unwind\n        ready\n"
+        );
+        assert_eq!(block_on(priced).unwrap(), (1, 2));
+
+        let timer = crate::timer::ManualTimer::new();
+        let hedged = hedge_with_timer(timer, || stock("replica"), core::time::Duration::from_millis(50), 2);
+        assert_eq!(describe_tree(&hedged), "race\n  leaf \"replica\"\n");
+        assert_eq!(block_on(hedged), Ok(1));
+    }
+
+    #[test]
+    fn validate_all_collects_every_error() {
+        block_on(async {
+            let checks = (1..=5).map(|id| async move {
+                yield_now().await;
+                if id % 2 == 0 {
+                    Err(format!("product {id} is out of stock"))
+                } else {
+                    Ok(id)
+                }
+            });
+
+            let errors = validate_all(checks).await.unwrap_err();
+
+            assert_eq!(
+                errors.into_vec(),
+                vec!["product 2 is out of stock", "product 4 is out of stock"]
+            );
+        })
+    }
+
+    #[test]
+    fn validate_combines_errors() {
+        block_on(async {
+            let a = async { Err::<i32, _>(vec!["bad code"]) };
+            let b = async { Err::<i32, _>(vec!["bad discount"]) };
+
+            assert_eq!(validate(a, b).await, Err(vec!["bad code", "bad discount"]));
+
+            let ok = validate(async { Ok::<_, String>(1) }, async { Ok(2) }).await;
+            assert_eq!(ok, Ok((1, 2)));
+        })
+    }
+
+    #[test]
+    fn join_settled_catching_isolates_panics() {
+        block_on(async {
+            let lookup = |id: i32| async move {
+                yield_now().await;
+                match id {
+                    1 => Ok(12.41),
+                    2 => Err("discontinued"),
+                    _ => panic!("unknown product {id}"),
+                }
+            };
+
+            let outcomes = join_settled_catching(vec![lookup(1), lookup(2), lookup(3)]).await;
+
+            assert!(matches!(outcomes[0], Outcome::Ok(price) if price == 12.41));
+            assert!(matches!(outcomes[1], Outcome::Err("discontinued")));
+            match &outcomes[2] {
+                Outcome::Panicked(panic) => assert_eq!(panic.message(), Some("unknown product 3")),
+                other => panic!("expected a panic, got {other:?}"),
+            }
+        })
+    }
+
+    #[test]
+    fn isolated_join_keeps_running_the_other_branch() {
+        block_on(async {
+            let (a, b) = join_futures_isolated(
+                async {
+                    yield_now().await;
+                    panic!("no product with id 3")
+                },
+                async {
+                    yield_now().await;
+                    yield_now().await;
+                    41.74
+                },
+            )
+            .await;
+
+            let a: Result<(), _> = a;
+            assert_eq!(a.unwrap_err().message(), Some("no product with id 3"));
+            assert_eq!(b.unwrap(), 41.74);
+        })
+    }
+
+    #[test]
+    fn isolated_validation_reports_panics_as_errors() {
+        #[derive(Debug, PartialEq)]
+        enum StockError {
+            OutOfStock(i32),
+            Crashed(String),
+        }
+
+        impl From<PanicPayload> for StockError {
+            fn from(panic: PanicPayload) -> Self {
+                StockError::Crashed(panic.message().unwrap_or_default().to_string())
+            }
+        }
+
+        block_on(async {
+            let check = |id: i32| async move {
+                yield_now().await;
+                match id {
+                    1 => Ok(id),
+                    2 => Err(StockError::OutOfStock(id)),
+                    _ => panic!("no product with id {id}"),
+                }
+            };
+
+            let errors = validate_all_isolated((1..=3).map(check)).await.unwrap_err();
+            assert_eq!(
+                errors.into_vec(),
+                vec![StockError::OutOfStock(2), StockError::Crashed("no product with id 3".into())]
+            );
+
+            let combined = combine_isolated(async { String::from("Local") }, async {
+                yield_now().await;
+                panic!("shop name service is down")
+            });
+            let combined: Result<String, _> = combined.await;
+            assert_eq!(combined.unwrap_err().message(), Some("shop name service is down"));
+        })
+    }
 
     #[tokio::test]
-    async fn identity_law() {
-        let result1 = identity(async move { 0 }.await);
-        let result2 = sequential(async move { 0 }, |x| async move { identity(x) }).await;
+    async fn abort_drops_the_whole_tree() {
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+
+        struct DropFlag(Arc<AtomicUsize>);
+
+        impl Drop for DropFlag {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let never = |flag: DropFlag| async move {
+            let _flag = flag;
+            std::future::pending::<i32>().await
+        };
+
+        let (handle, token) = abort_pair();
+        let tree = combine_with(
+            join_futures(never(DropFlag(dropped.clone())), async { 1 }),
+            sequence(async {}, never(DropFlag(dropped.clone()))),
+            |(a, b), c| a + b + c,
+        );
+
+        let task = tokio::spawn(abortable(tree, token));
+        tokio::task::yield_now().await;
+        handle.abort();
+
+        assert_eq!(task.await.unwrap(), Err(Aborted));
+        assert_eq!(dropped.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn hedge_launches_a_copy_after_the_delay() {
+        use crate::timer::ManualTimer;
+        use std::{
+            sync::atomic::{AtomicUsize, Ordering},
+            time::Duration,
+        };
 
-        assert_eq!(result1, result2);
+        let timer = ManualTimer::new();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let factory = {
+            let calls = calls.clone();
+            move || {
+                let attempt = calls.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    if attempt == 0 {
+                        // The first attempt is stuck on a slow replica
+                        std::future::pending::<()>().await;
+                    }
+                    Ok::<_, ()>(attempt)
+                }
+            }
+        };
+
+        let hedged = hedge_with_timer(timer.clone(), factory, Duration::from_millis(50), 3);
+        let mut hedged = std::pin::pin!(hedged);
+        let waker = Waker::noop();
+        let mut cx = Context::from_waker(waker);
+
+        assert!(hedged.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        timer.advance(Duration::from_millis(50));
+
+        let sink = Arc::new(crate::metrics::InMemorySink::new());
+        let res = crate::metrics::with_sink(sink.clone(), || hedged.as_mut().poll(&mut cx));
+
+        assert_eq!(res, Poll::Ready(Ok(1)));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        let retries = [("combinator", "hedge"), ("reason", "delay")];
+        assert_eq!(sink.counter(crate::metrics::RETRIES, &retries), 1);
     }
 
     #[tokio::test]
-    async fn associativity() {
-        let a = async { 1 };
-        let b = |x: i32| async move { x + 1 };
-        let c = |x: i32| async move { x * 2 };
+    async fn shared_wakes_every_clone() {
+        let (tx, rx) = tokio::sync::oneshot::channel::<String>();
+        let info = shared(async move { rx.await.unwrap() });
+
+        let first = tokio::spawn(info.clone());
+        let second = tokio::spawn(map(info.clone(), |name: String| name.len()));
+        tokio::task::yield_now().await;
+
+        tx.send(String::from("Local shop")).unwrap();
+
+        assert_eq!(first.await.unwrap(), "Local shop");
+        assert_eq!(second.await.unwrap(), 10);
+        assert_eq!(info.await, "Local shop");
+    }
+
+    #[test]
+    fn combinators_borrow_local_data() {
+        block_on(async {
+            let prices = vec![1241, 4174];
+            let shop = String::from("Local shop");
+
+            let prices = &prices;
+            let total = sequential(async { 2 }, |factor| async move {
+                yield_now().await;
+                prices.iter().sum::<i32>() * factor
+            });
+            let greeting = combine(
+                async {
+                    yield_now().await;
+                    shop.clone()
+                },
+                async { String::from("!") },
+            );
+
+            assert_eq!(total.await, 10830);
+            assert_eq!(greeting.await, "Local shop!");
+        })
+    }
+
+    #[cfg(feature = "tracing")]
+    /// Runs `f` and returns every event it logged at debug level or above.
+    fn capture_events(f: impl FnOnce()) -> String {
+        #[derive(Clone, Default)]
+        struct Captured(Arc<Mutex<Vec<u8>>>);
+
+        impl std::io::Write for Captured {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .with_max_level(tracing::Level::DEBUG)
+            .finish();
 
-        // Applying sequential(sequential(A, B), C)
-        let res1 = sequential(sequential(a, b), c).await;
+        tracing::subscriber::with_default(subscriber, f);
 
-        // Reset a + to avoid using moved values
-        let a = async { 1 };
+        let out = captured.0.lock().unwrap().clone();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn traced_reports_completion_and_polls() {
+        let out = capture_events(|| {
+            block_on(join_all([yield_now(), yield_now()]).traced("restock"));
+        });
+
+        assert!(out.contains("restock completed"), "{out}");
+        assert!(out.contains("polls=2"), "{out}");
+        assert!(out.contains("combinator=\"join_all\" branch=1 ok=true"), "{out}");
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn joins_report_branch_completion() {
+        let out = capture_events(|| {
+            block_on(join_futures(yield_now(), ready(1)));
+            let _ = block_on(try_join(ready(Ok::<_, ()>(1)), ready(Err::<i32, _>(()))));
+        });
+
+        let events: Vec<&str> = out.lines().filter(|line| line.contains("branch completed")).collect();
+        assert_eq!(events.len(), 4, "{out}");
+        assert!(events[0].contains("combinator=\"join\" branch=1 ok=true"), "{out}");
+        assert!(events[1].contains("combinator=\"join\" branch=0 ok=true"), "{out}");
+        assert!(events[3].contains("combinator=\"try_join\" branch=1 ok=false"), "{out}");
+    }
+
+    fn assert_send<T: Send>(_: &T) {}
+    fn assert_sync<T: Sync>(_: &T) {}
+
+    #[test]
+    fn combinators_propagate_auto_traits() {
+        let pipeline = map(
+            join_futures(
+                try_join(async { Ok::<_, ()>(1) }, async { Ok(2) }),
+                sequential(async { 3 }, |x| async move { x * 2 }),
+            ),
+            |(a, b)| (a, b),
+        );
+        assert_send(&pipeline);
+        assert_sync(&pipeline);
+
+        let monoids = combine(async { String::from("a") }, async { String::from("b") });
+        assert_send(&monoids);
+        assert_sync(&monoids);
+
+        let settled = join_settled_catching(vec![async { Ok::<_, ()>(1) }]);
+        assert_send(&settled);
+        assert_sync(&settled);
+
+        let (_, token) = abort_pair();
+        let info = shared(async { String::from("Local shop") });
+        let abortable = abortable(select(info.clone(), ready(String::new())), token);
+        assert_send(&abortable);
+        assert_sync(&abortable);
+
+        let boxed = combine_boxed(ready(String::new()), fut_id());
+        assert_send(&boxed);
+    }
 
-        // Applying sequential(A, sequential(B, C))
-        let res2 = sequential(a, move |x| sequential(b(x), c)).await;
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn sequential_pipelines_can_be_spawned() {
+        let pipeline = sequential(async { 2 }, |x| async move {
+            tokio::task::yield_now().await;
+            x * 21
+        });
 
-        assert_eq!(res1, res2, "Sequential composition should be associative");
+        assert_eq!(tokio::spawn(pipeline).await.unwrap(), 42);
     }
 }
@@ -1,2 +0,0 @@
-pub mod case1;
-pub mod case2;
\ No newline at end of file
@@ -0,0 +1,355 @@
+//! A minimal executor built from std only, so the combinators can be driven without tokio.
+
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    future::Future,
+    pin::{pin, Pin},
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll, Wake, Waker},
+    thread::{self, Thread},
+};
+
+/// Wakes the thread blocked in [`block_on`] or [`LocalExecutor::run_until`].
+struct ThreadWaker {
+    thread: Thread,
+    notified: AtomicBool,
+}
+
+impl ThreadWaker {
+    fn current() -> Arc<Self> {
+        Arc::new(ThreadWaker {
+            thread: thread::current(),
+            notified: AtomicBool::new(false),
+        })
+    }
+
+    /// Clears the notification, returning whether there was one.
+    fn take_notified(&self) -> bool {
+        self.notified.swap(false, Ordering::SeqCst)
+    }
+
+    fn is_notified(&self) -> bool {
+        self.notified.load(Ordering::SeqCst)
+    }
+}
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.notified.store(true, Ordering::SeqCst);
+        self.thread.unpark();
+    }
+}
+
+/// Runs `future` to completion on the current thread, parking it while the future is pending.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let thread_waker = ThreadWaker::current();
+    let waker = Waker::from(thread_waker.clone());
+    let mut cx = Context::from_waker(&waker);
+
+    loop {
+        if let Poll::Ready(res) = future.as_mut().poll(&mut cx) {
+            return res;
+        }
+
+        // `park` may return spuriously, only poll again once actually woken
+        while !thread_waker.take_notified() {
+            thread::park();
+        }
+    }
+}
+
+/// Yields once back to the executor, letting other tasks run.
+pub fn yield_now() -> YieldNow {
+    YieldNow { yielded: false }
+}
+
+pub struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+
+        self.yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+type LocalTaskFuture = Pin<Box<dyn Future<Output = ()>>>;
+
+/// Queue of woken task ids. Shared with wakers, which may be sent to other threads.
+struct ReadyQueue {
+    ready: Mutex<VecDeque<usize>>,
+    thread: Thread,
+}
+
+struct TaskWaker {
+    id: usize,
+    queue: Arc<ReadyQueue>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.queue.ready.lock().unwrap().push_back(self.id);
+        self.queue.thread.unpark();
+    }
+}
+
+struct Tasks {
+    slots: Vec<Option<LocalTaskFuture>>,
+    free: Vec<usize>,
+    // Spawned while tasks were being polled, moved into `slots` on the next tick
+    spawned: Vec<LocalTaskFuture>,
+}
+
+/// Single-threaded executor for `!Send` futures.
+///
+/// Spawned tasks only make progress while the executor is driven by [`LocalExecutor::run_until`]
+/// or [`LocalExecutor::run`].
+#[derive(Clone)]
+pub struct LocalExecutor {
+    tasks: Rc<RefCell<Tasks>>,
+    queue: Arc<ReadyQueue>,
+}
+
+impl LocalExecutor {
+    pub fn new() -> Self {
+        LocalExecutor {
+            tasks: Rc::new(RefCell::new(Tasks {
+                slots: Vec::new(),
+                free: Vec::new(),
+                spawned: Vec::new(),
+            })),
+            queue: Arc::new(ReadyQueue {
+                ready: Mutex::new(VecDeque::new()),
+                thread: thread::current(),
+            }),
+        }
+    }
+
+    /// Spawns `future` onto the executor. Clones of the executor can be moved into tasks to
+    /// spawn from inside them.
+    pub fn spawn<F>(&self, future: F) -> LocalTask<F::Output>
+    where
+        F: Future + 'static,
+    {
+        let slot = Rc::new(RefCell::new(TaskSlot {
+            output: None,
+            waker: None,
+        }));
+
+        let task_slot = slot.clone();
+        let task = async move {
+            let output = future.await;
+            let mut slot = task_slot.borrow_mut();
+            slot.output = Some(output);
+            if let Some(waker) = slot.waker.take() {
+                waker.wake();
+            }
+        };
+
+        self.tasks.borrow_mut().spawned.push(Box::pin(task));
+
+        LocalTask { slot }
+    }
+
+    /// Drives the spawned tasks until `future` completes, returning its output.
+    pub fn run_until<F: Future>(&self, future: F) -> F::Output {
+        let mut future = pin!(future);
+        let main_waker = ThreadWaker::current();
+        let waker = Waker::from(main_waker.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        let mut first_poll = true;
+        loop {
+            if std::mem::take(&mut first_poll) || main_waker.take_notified() {
+                if let Poll::Ready(res) = future.as_mut().poll(&mut cx) {
+                    return res;
+                }
+            }
+
+            if !self.tick() && !main_waker.is_notified() {
+                thread::park();
+            }
+        }
+    }
+
+    /// Drives the spawned tasks until all of them have completed.
+    pub fn run(&self) {
+        loop {
+            let progressed = self.tick();
+
+            let tasks = self.tasks.borrow();
+            if tasks.spawned.is_empty() && tasks.slots.iter().all(Option::is_none) {
+                return;
+            }
+            drop(tasks);
+
+            if !progressed {
+                thread::park();
+            }
+        }
+    }
+
+    /// Polls the newly spawned and woken tasks once, returning whether any task was polled.
+    fn tick(&self) -> bool {
+        self.schedule_spawned();
+
+        let ready: Vec<usize> = self.queue.ready.lock().unwrap().drain(..).collect();
+        for &id in &ready {
+            // Taken out of its slot while polled, so the task can spawn without a double borrow
+            let task = self.tasks.borrow_mut().slots.get_mut(id).and_then(Option::take);
+            let Some(mut task) = task else {
+                // Spurious wake of a task that already completed
+                continue;
+            };
+
+            let waker = Waker::from(Arc::new(TaskWaker {
+                id,
+                queue: self.queue.clone(),
+            }));
+
+            match task.as_mut().poll(&mut Context::from_waker(&waker)) {
+                Poll::Ready(()) => self.tasks.borrow_mut().free.push(id),
+                Poll::Pending => self.tasks.borrow_mut().slots[id] = Some(task),
+            }
+        }
+
+        !ready.is_empty()
+    }
+
+    fn schedule_spawned(&self) {
+        let mut tasks = self.tasks.borrow_mut();
+        let spawned = std::mem::take(&mut tasks.spawned);
+
+        let mut ready = self.queue.ready.lock().unwrap();
+        for task in spawned {
+            let id = match tasks.free.pop() {
+                Some(id) => {
+                    tasks.slots[id] = Some(task);
+                    id
+                }
+                None => {
+                    tasks.slots.push(Some(task));
+                    tasks.slots.len() - 1
+                }
+            };
+            ready.push_back(id);
+        }
+    }
+}
+
+impl Default for LocalExecutor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct TaskSlot<T> {
+    output: Option<T>,
+    waker: Option<Waker>,
+}
+
+/// Resolves to the output of a task spawned on a [`LocalExecutor`].
+pub struct LocalTask<T> {
+    slot: Rc<RefCell<TaskSlot<T>>>,
+}
+
+impl<T> Future for LocalTask<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut slot = self.slot.borrow_mut();
+        match slot.output.take() {
+            Some(output) => Poll::Ready(output),
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc, time::Duration};
+
+    use crate::comb::*;
+    use crate::executor::*;
+
+    #[test]
+    fn block_on_wakes_from_another_thread() {
+        let (tx, rx) = std::sync::mpsc::channel::<Waker>();
+
+        let waker_thread = thread::spawn(move || {
+            let waker = rx.recv().unwrap();
+            thread::sleep(Duration::from_millis(10));
+            waker.wake();
+        });
+
+        let mut tx = Some(tx);
+        let res = block_on(std::future::poll_fn(move |cx| {
+            if let Some(tx) = tx.take() {
+                tx.send(cx.waker().clone()).unwrap();
+                return Poll::Pending;
+            }
+            Poll::Ready(7)
+        }));
+
+        waker_thread.join().unwrap();
+        assert_eq!(res, 7);
+    }
+
+    #[test]
+    fn local_executor_runs_spawned_tasks() {
+        let executor = LocalExecutor::new();
+        let order = Rc::new(RefCell::new(Vec::new()));
+
+        let log = |name: &'static str| {
+            let order = order.clone();
+            async move {
+                order.borrow_mut().push(name);
+                yield_now().await;
+                order.borrow_mut().push(name);
+                name.len()
+            }
+        };
+
+        let first = executor.spawn(log("first"));
+        let second = executor.spawn(log("second"));
+        let lengths = executor.run_until(join_futures(first, second));
+
+        assert_eq!(lengths, (5, 6));
+        assert_eq!(*order.borrow(), vec!["first", "second", "first", "second"]);
+
+        let spawner = executor.clone();
+        let nested = Rc::new(Cell::new(0));
+        let count = nested.clone();
+        executor.spawn(async move {
+            spawner.spawn(async move { count.set(count.get() + 1) }).await;
+        });
+        executor.run();
+
+        assert_eq!(nested.get(), 1);
+    }
+}
@@ -0,0 +1,353 @@
+//! Reusable property checks for the algebraic laws the combinators are expected to uphold.
+//!
+//! Every check draws its inputs from a seeded [`Gen`], so a failing case can be replayed
+//! by running the same check with the seed reported in the [`LawViolation`].
+
+use core::{fmt::Debug, future::Future};
+
+use alloc::{format, string::String};
+
+use crate::comb::*;
+
+/// Small deterministic random generator (SplitMix64), good enough for driving property checks.
+#[derive(Debug, Clone)]
+pub struct Gen {
+    state: u64,
+}
+
+impl Gen {
+    pub fn new(seed: u64) -> Self {
+        Gen { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform value in `low..high`.
+    pub fn range(&mut self, low: i64, high: i64) -> i64 {
+        assert!(low < high, "Gen::range called with an empty range");
+        // Computed modulo 2^64, so the full `i64` range doesn't overflow
+        let span = high.wrapping_sub(low) as u64;
+        low.wrapping_add((self.next_u64() % span) as i64)
+    }
+
+    pub fn bool(&mut self) -> bool {
+        self.next_u64() & 1 == 1
+    }
+}
+
+/// Types that can be randomly generated for law checks.
+pub trait Arbitrary: Sized {
+    fn arbitrary(g: &mut Gen) -> Self;
+}
+
+impl Arbitrary for bool {
+    fn arbitrary(g: &mut Gen) -> Self {
+        g.bool()
+    }
+}
+
+// Kept small so that laws involving arithmetic don't trip overflow checks
+impl Arbitrary for i32 {
+    fn arbitrary(g: &mut Gen) -> Self {
+        g.range(-10_000, 10_000) as i32
+    }
+}
+
+impl Arbitrary for i64 {
+    fn arbitrary(g: &mut Gen) -> Self {
+        g.range(-1_000_000, 1_000_000)
+    }
+}
+
+impl Arbitrary for u32 {
+    fn arbitrary(g: &mut Gen) -> Self {
+        g.range(0, 10_000) as u32
+    }
+}
+
+impl<A: Arbitrary, B: Arbitrary> Arbitrary for (A, B) {
+    fn arbitrary(g: &mut Gen) -> Self {
+        (A::arbitrary(g), B::arbitrary(g))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LawConfig {
+    pub seed: u64,
+    pub cases: usize,
+}
+
+impl Default for LawConfig {
+    fn default() -> Self {
+        LawConfig {
+            seed: 0x5EED,
+            cases: 100,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LawViolation {
+    pub law: &'static str,
+    pub seed: u64,
+    pub case: usize,
+    pub counterexample: String,
+}
+
+impl core::fmt::Display for LawViolation {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{} violated on case {} (seed {:#x}): {}",
+            self.law, self.case, self.seed, self.counterexample
+        )
+    }
+}
+
+impl core::error::Error for LawViolation {}
+
+fn check<T: Debug>(
+    law: &'static str,
+    config: &LawConfig,
+    case: usize,
+    input: &T,
+    left: &impl Debug,
+    right: &impl Debug,
+    holds: bool,
+) -> Result<(), LawViolation> {
+    if holds {
+        return Ok(());
+    }
+
+    Err(LawViolation {
+        law,
+        seed: config.seed,
+        case,
+        counterexample: format!("input {input:?}: {left:?} != {right:?}"),
+    })
+}
+
+/// `identity().combine(a) == a == a.combine(identity())`
+pub fn monoid_identity<T>(config: &LawConfig) -> Result<(), LawViolation>
+where
+    T: Monoid + Arbitrary + PartialEq + Debug,
+{
+    let mut g = Gen::new(config.seed);
+    for case in 0..config.cases {
+        let a = T::arbitrary(&mut g);
+
+        let left = T::identity().combine(&a);
+        check("monoid left identity", config, case, &a, &left, &a, left == a)?;
+
+        let right = a.combine(&T::identity());
+        check("monoid right identity", config, case, &a, &right, &a, right == a)?;
+    }
+    Ok(())
+}
+
+/// `(a <> b) <> c == a <> (b <> c)`
+pub fn monoid_associativity<T>(config: &LawConfig) -> Result<(), LawViolation>
+where
+    T: Monoid + Arbitrary + PartialEq + Debug,
+{
+    let mut g = Gen::new(config.seed);
+    for case in 0..config.cases {
+        let (a, b, c) = (T::arbitrary(&mut g), T::arbitrary(&mut g), T::arbitrary(&mut g));
+
+        let left = a.combine(&b).combine(&c);
+        let right = a.combine(&b.combine(&c));
+        check("monoid associativity", config, case, &(&a, &b, &c), &left, &right, left == right)?;
+    }
+    Ok(())
+}
+
+/// Combining futures with [`combine`] must agree with combining their outputs directly.
+pub async fn monoid_combine_futures<T>(config: &LawConfig) -> Result<(), LawViolation>
+where
+    T: Monoid + Arbitrary + PartialEq + Debug + Clone,
+{
+    let mut g = Gen::new(config.seed);
+    for case in 0..config.cases {
+        let (a, b) = (T::arbitrary(&mut g), T::arbitrary(&mut g));
+
+        let left = combine(ready(a.clone()), ready(b.clone())).await;
+        let right = a.combine(&b);
+        check("monoid combine", config, case, &(&a, &b), &left, &right, left == right)?;
+    }
+    Ok(())
+}
+
+/// `fmap(m, id) == m`, where `mk` builds the future under test from a generated value.
+pub async fn functor_identity<T, Mk, Fut>(config: &LawConfig, mk: Mk) -> Result<(), LawViolation>
+where
+    T: Arbitrary + PartialEq + Debug + Clone,
+    Mk: Fn(T) -> Fut,
+    Fut: Future<Output = T>,
+{
+    let mut g = Gen::new(config.seed);
+    for case in 0..config.cases {
+        let a = T::arbitrary(&mut g);
+
+        let left = mk(a.clone()).fmap(|x| x).await;
+        let right = mk(a.clone()).await;
+        check("functor identity", config, case, &a, &left, &right, left == right)?;
+    }
+    Ok(())
+}
+
+/// `fmap(m, g . f) == fmap(fmap(m, f), g)`
+pub async fn functor_composition<T, Mk, Fut, F, G>(
+    config: &LawConfig,
+    mk: Mk,
+    f: F,
+    g: G,
+) -> Result<(), LawViolation>
+where
+    T: Arbitrary + PartialEq + Debug + Clone,
+    Mk: Fn(T) -> Fut,
+    Fut: Future<Output = T>,
+    F: Fn(T) -> T + Clone,
+    G: Fn(T) -> T + Clone,
+{
+    let mut gen = Gen::new(config.seed);
+    for case in 0..config.cases {
+        let a = T::arbitrary(&mut gen);
+
+        let (f1, g1) = (f.clone(), g.clone());
+        let left = mk(a.clone()).fmap(move |x| g1(f1(x))).await;
+        let right = mk(a.clone()).fmap(f.clone()).fmap(g.clone()).await;
+        check("functor composition", config, case, &a, &left, &right, left == right)?;
+    }
+    Ok(())
+}
+
+/// Left identity, right identity and associativity of [`Monad::bind`].
+///
+/// `mk` lifts a value into the monadic future, `f` and `h` are the continuations being chained.
+pub async fn monad_laws<T, Mk, Fut, F, H, FF, HF>(
+    config: &LawConfig,
+    mk: Mk,
+    f: F,
+    h: H,
+) -> Result<(), LawViolation>
+where
+    T: Arbitrary + PartialEq + Debug + Clone,
+    Mk: Fn(T) -> Fut,
+    Fut: Future<Output = T>,
+    F: Fn(T) -> FF + Clone,
+    FF: Future<Output = T>,
+    H: Fn(T) -> HF + Clone,
+    HF: Future<Output = T>,
+{
+    let mut g = Gen::new(config.seed);
+    for case in 0..config.cases {
+        let a = T::arbitrary(&mut g);
+
+        // pure(a) >>= f == f(a)
+        let left = pure(a.clone()).bind(f.clone()).await;
+        let right = f(a.clone()).await;
+        check("monad left identity", config, case, &a, &left, &right, left == right)?;
+
+        // m >>= pure == m
+        let left = mk(a.clone()).bind(pure).await;
+        let right = mk(a.clone()).await;
+        check("monad right identity", config, case, &a, &left, &right, left == right)?;
+
+        // (m >>= f) >>= h == m >>= (\x -> f(x) >>= h)
+        let left = mk(a.clone()).bind(f.clone()).bind(h.clone()).await;
+        let (f1, h1) = (f.clone(), h.clone());
+        let right = mk(a.clone()).bind(move |x| f1(x).bind(h1)).await;
+        check("monad associativity", config, case, &a, &left, &right, left == right)?;
+    }
+    Ok(())
+}
+
+/// `join(a, b) == swap(join(b, a))` for a two-way join combinator such as [`join_futures`].
+pub async fn join_commutativity<T, Mk, Fut, J, JF>(
+    config: &LawConfig,
+    mk: Mk,
+    join: J,
+) -> Result<(), LawViolation>
+where
+    T: Arbitrary + PartialEq + Debug + Clone,
+    Mk: Fn(T) -> Fut,
+    Fut: Future<Output = T>,
+    J: Fn(Fut, Fut) -> JF,
+    JF: Future<Output = (T, T)>,
+{
+    let mut g = Gen::new(config.seed);
+    for case in 0..config.cases {
+        let (a, b) = (T::arbitrary(&mut g), T::arbitrary(&mut g));
+
+        let left = join(mk(a.clone()), mk(b.clone())).await;
+        let (y, x) = join(mk(b.clone()), mk(a.clone())).await;
+        let right = (x, y);
+        check("join commutativity", config, case, &(&a, &b), &left, &right, left == right)?;
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use crate::comb::*;
+    use crate::executor::{block_on, yield_now};
+    use crate::laws::*;
+
+    #[test]
+    fn gen_is_deterministic() {
+        let mut a = Gen::new(7);
+        let mut b = Gen::new(7);
+
+        let xs: Vec<i32> = (0..10).map(|_| i32::arbitrary(&mut a)).collect();
+        let ys: Vec<i32> = (0..10).map(|_| i32::arbitrary(&mut b)).collect();
+
+        assert_eq!(xs, ys);
+    }
+
+    #[test]
+    fn gen_range_covers_extreme_bounds() {
+        let mut g = Gen::new(7);
+        for _ in 0..100 {
+            let x = g.range(i64::MIN, i64::MAX);
+            assert!(x < i64::MAX);
+            assert!((-3..-1).contains(&g.range(-3, -1)));
+        }
+    }
+
+    #[test]
+    fn combinators_obey_laws() {
+        block_on(async {
+            let config = LawConfig::default();
+            let mk = |x: i32| async move {
+                yield_now().await;
+                x
+            };
+
+            functor_identity(&config, mk).await.unwrap();
+            functor_composition(&config, mk, |x| x + 1, |x| x * 2).await.unwrap();
+            monad_laws(&config, mk, |x| async move { x - 3 }, |x| async move { x * 7 })
+                .await
+                .unwrap();
+            join_commutativity(&config, mk, join_futures).await.unwrap();
+        })
+    }
+
+    #[test]
+    fn violation_reports_the_law() {
+        block_on(async {
+            let config = LawConfig { seed: 1, cases: 10 };
+            let biased = |a, b| map(join_futures(a, b), |(x, _): (i32, i32)| (x, x));
+
+            let err = join_commutativity(&config, pure, biased).await.unwrap_err();
+
+            assert_eq!(err.law, "join commutativity");
+            assert_eq!(err.seed, 1);
+        })
+    }
+}
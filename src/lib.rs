@@ -0,0 +1,35 @@
+//! Future combinators built on `core::future`: joins, sequencing, mapping, monoidal
+//! combination and friends, usable without an allocator, with `alloc`, or with `std`.
+//!
+//! Optional integrations are enabled with cargo features:
+//! - `tokio`: spawning combinators and tokio's timer, and the Prometheus endpoint of [`metrics`]
+//! - `tracing`: `tracing` spans, completion events and poll counts for combinators wrapped
+//!   with [`comb::Traceable::traced`]
+//! - `http`: only used by the `case1` example, to fetch its scores over HTTP with `reqwest`
+//! - `derive`: `serde` derives on the public data types
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+pub mod comb;
+pub mod timer;
+
+#[cfg(feature = "alloc")]
+pub mod laws;
+
+#[cfg(feature = "std")]
+pub mod executor;
+
+#[cfg(feature = "std")]
+pub mod timeline;
+
+#[cfg(feature = "std")]
+pub mod profile;
+
+#[cfg(feature = "std")]
+pub mod metrics;
+
+#[cfg(feature = "tokio")]
+pub mod spawn;
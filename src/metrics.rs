@@ -0,0 +1,495 @@
+//! Counters and histograms reported by the combinators to a pluggable [`MetricsSink`].
+//!
+//! Futures wrapped with [`Meterable::metered`] report their latency and outcome, the joins,
+//! selects and races report the same for each of their branches, and
+//! [`hedge_with_timer`](crate::comb::hedge_with_timer) reports every extra copy it launches as a retry. Nothing is
+//! collected until a sink is installed with [`set_global_sink`] or [`with_sink`].
+//! [`InMemorySink`] keeps everything in memory and renders it in the Prometheus text format,
+//! which `serve_prometheus` exposes over TCP.
+
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    fmt::Write,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, RwLock},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use crate::comb::{Aborted, Describe, Kind, NonEmptyVec, Outcome, PanicPayload};
+
+/// Histogram of how long metered futures took, in seconds.
+pub const LATENCY: &str = "combinator_duration_seconds";
+/// Counter of metered futures by `status`.
+pub const OUTCOMES: &str = "combinator_outcomes_total";
+/// Histogram of how long the branches of joins, selects and races took, in seconds.
+pub const BRANCH_LATENCY: &str = "combinator_branch_duration_seconds";
+/// Counter of completed branches by `status`, `error` when a branch failed its combinator.
+pub const BRANCH_OUTCOMES: &str = "combinator_branch_outcomes_total";
+/// Counter of retried attempts.
+pub const RETRIES: &str = "combinator_retries_total";
+
+pub type Labels<'a> = &'a [(&'static str, &'static str)];
+
+/// Receives the metrics reported by the combinators.
+pub trait MetricsSink: Send + Sync {
+    fn increment_counter(&self, name: &'static str, labels: Labels<'_>, by: u64);
+
+    fn record_histogram(&self, name: &'static str, labels: Labels<'_>, value: f64);
+}
+
+static GLOBAL_SINK: RwLock<Option<Arc<dyn MetricsSink>>> = RwLock::new(None);
+
+thread_local! {
+    static LOCAL_SINK: RefCell<Option<Arc<dyn MetricsSink>>> = const { RefCell::new(None) };
+}
+
+/// Installs the sink used by every thread that has no sink of its own, see [`with_sink`].
+pub fn set_global_sink(sink: Arc<dyn MetricsSink>) {
+    *GLOBAL_SINK.write().unwrap() = Some(sink);
+}
+
+pub fn clear_global_sink() {
+    *GLOBAL_SINK.write().unwrap() = None;
+}
+
+/// Runs `f` with `sink` receiving the metrics reported on the current thread, e.g. to
+/// collect the metrics of a single test.
+pub fn with_sink<R>(sink: Arc<dyn MetricsSink>, f: impl FnOnce() -> R) -> R {
+    struct Restore(Option<Arc<dyn MetricsSink>>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            LOCAL_SINK.with(|local| *local.borrow_mut() = previous);
+        }
+    }
+
+    let _restore = Restore(LOCAL_SINK.with(|local| local.borrow_mut().replace(sink)));
+    f()
+}
+
+fn report(f: impl FnOnce(&dyn MetricsSink)) {
+    let local = LOCAL_SINK.with(|local| local.borrow().clone());
+    if let Some(sink) = local {
+        return f(&*sink);
+    }
+
+    if let Some(sink) = GLOBAL_SINK.read().unwrap().as_ref() {
+        f(&**sink);
+    }
+}
+
+pub(crate) fn increment(name: &'static str, labels: Labels<'_>) {
+    report(|sink| sink.increment_counter(name, labels, 1));
+}
+
+/// Reports a branch of a `combinator` that completed after `elapsed`, see [`BRANCH_LATENCY`].
+pub(crate) fn branch_completed(combinator: &'static str, elapsed: Duration, ok: bool) {
+    let status = if ok { Status::Success } else { Status::Error };
+    report(|sink| {
+        sink.record_histogram(BRANCH_LATENCY, &[("combinator", combinator)], elapsed.as_secs_f64());
+        sink.increment_counter(
+            BRANCH_OUTCOMES,
+            &[("combinator", combinator), ("status", status.as_str())],
+            1,
+        );
+    });
+}
+
+/// How a metered future ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Status {
+    Success,
+    Error,
+    Timeout,
+    Panicked,
+    /// Dropped before completing.
+    Cancelled,
+}
+
+impl Status {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Status::Success => "success",
+            Status::Error => "error",
+            Status::Timeout => "timeout",
+            Status::Panicked => "panicked",
+            Status::Cancelled => "cancelled",
+        }
+    }
+}
+
+/// Outputs that can be counted as a success or a failure by [`Metered`].
+pub trait Classify {
+    fn status(&self) -> Status;
+}
+
+/// Errors that can tell which [`Status`] they stand for. Anything that isn't a timeout,
+/// panic or cancellation can use the default.
+pub trait ErrorStatus {
+    fn status(&self) -> Status {
+        Status::Error
+    }
+}
+
+impl<T, E: ErrorStatus> Classify for Result<T, E> {
+    fn status(&self) -> Status {
+        match self {
+            Ok(_) => Status::Success,
+            Err(e) => e.status(),
+        }
+    }
+}
+
+impl<T, E: ErrorStatus> Classify for Outcome<T, E> {
+    fn status(&self) -> Status {
+        match self {
+            Outcome::Ok(_) => Status::Success,
+            Outcome::Err(e) => e.status(),
+            Outcome::Panicked(_) => Status::Panicked,
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl ErrorStatus for tokio::time::error::Elapsed {
+    fn status(&self) -> Status {
+        Status::Timeout
+    }
+}
+
+impl ErrorStatus for Aborted {
+    fn status(&self) -> Status {
+        Status::Cancelled
+    }
+}
+
+impl ErrorStatus for PanicPayload {
+    fn status(&self) -> Status {
+        Status::Panicked
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl ErrorStatus for crate::spawn::SpawnError {
+    fn status(&self) -> Status {
+        match self {
+            crate::spawn::SpawnError::Panicked(_) => Status::Panicked,
+            crate::spawn::SpawnError::Cancelled => Status::Cancelled,
+        }
+    }
+}
+
+impl ErrorStatus for () {}
+impl ErrorStatus for String {}
+impl ErrorStatus for &'static str {}
+impl ErrorStatus for std::io::Error {}
+impl ErrorStatus for Box<dyn std::error::Error + Send + Sync> {}
+impl<E> ErrorStatus for NonEmptyVec<E> {}
+
+/// A future reporting its latency and outcome, created with [`Meterable::metered`].
+pub struct Metered<F> {
+    inner: F,
+    label: &'static str,
+    started: Option<Instant>,
+    done: bool,
+}
+
+pub trait Meterable: Future + Sized {
+    /// Reports how long the future took, from its first poll, under [`LATENCY`] and how it
+    /// ended under [`OUTCOMES`], both labelled with `label`.
+    fn metered(self, label: &'static str) -> Metered<Self>
+    where
+        Self::Output: Classify,
+    {
+        Metered {
+            inner: self,
+            label,
+            started: None,
+            done: false,
+        }
+    }
+}
+
+impl<F: Future> Meterable for F {}
+
+impl<F> Metered<F> {
+    fn finish(&mut self, status: Status) {
+        self.done = true;
+        let elapsed = self.started.map_or(Duration::ZERO, |started| started.elapsed());
+        let label = self.label;
+
+        report(|sink| {
+            sink.record_histogram(LATENCY, &[("label", label)], elapsed.as_secs_f64());
+            sink.increment_counter(OUTCOMES, &[("label", label), ("status", status.as_str())], 1);
+        });
+    }
+}
+
+impl<F> Future for Metered<F>
+where
+    F: Future,
+    F::Output: Classify,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        this.started.get_or_insert_with(Instant::now);
+
+        let res = unsafe { Pin::new_unchecked(&mut this.inner) }.poll(cx);
+        if let Poll::Ready(output) = &res {
+            this.finish(output.status());
+        }
+        res
+    }
+}
+
+impl<F> Drop for Metered<F> {
+    fn drop(&mut self) {
+        if self.started.is_some() && !self.done {
+            self.finish(Status::Cancelled);
+        }
+    }
+}
+
+impl<F: Describe> Describe for Metered<F> {
+    fn kind(&self) -> Kind {
+        self.inner.kind()
+    }
+
+    fn label(&self) -> Option<&'static str> {
+        Some(self.label)
+    }
+
+    fn for_each_child(&self, visit: &mut dyn FnMut(&dyn Describe)) {
+        self.inner.for_each_child(visit)
+    }
+}
+
+type Key = (&'static str, Vec<(&'static str, &'static str)>);
+
+/// Upper bounds, in seconds, of the [`InMemorySink`] histogram buckets.
+pub const BUCKETS: [f64; 10] = [0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0];
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Histogram {
+    /// Non-cumulative count per bucket of [`BUCKETS`], plus the overflow bucket.
+    pub buckets: [u64; BUCKETS.len() + 1],
+    pub sum: f64,
+    pub count: u64,
+}
+
+/// Keeps every metric in memory, for tests and for [`InMemorySink::render_prometheus`].
+#[derive(Debug, Default)]
+pub struct InMemorySink {
+    counters: Mutex<BTreeMap<Key, u64>>,
+    histograms: Mutex<BTreeMap<Key, Histogram>>,
+}
+
+impl InMemorySink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Value of a counter, zero if it was never incremented.
+    pub fn counter(&self, name: &'static str, labels: Labels<'_>) -> u64 {
+        let counters = self.counters.lock().unwrap();
+        counters.get(&(name, labels.to_vec())).copied().unwrap_or(0)
+    }
+
+    pub fn histogram(&self, name: &'static str, labels: Labels<'_>) -> Option<Histogram> {
+        self.histograms.lock().unwrap().get(&(name, labels.to_vec())).cloned()
+    }
+
+    /// Every metric in the Prometheus text exposition format.
+    pub fn render_prometheus(&self) -> String {
+        let mut out = String::new();
+
+        let mut last = "";
+        for ((name, labels), value) in self.counters.lock().unwrap().iter() {
+            if *name != last {
+                writeln!(out, "# TYPE {name} counter").unwrap();
+                last = name;
+            }
+            writeln!(out, "{name}{} {value}", render_labels(labels, None)).unwrap();
+        }
+
+        let mut last = "";
+        for ((name, labels), histogram) in self.histograms.lock().unwrap().iter() {
+            if *name != last {
+                writeln!(out, "# TYPE {name} histogram").unwrap();
+                last = name;
+            }
+
+            let mut cumulative = 0;
+            for (i, count) in histogram.buckets.iter().enumerate() {
+                cumulative += count;
+                let le = BUCKETS.get(i).map_or("+Inf".to_string(), |le| le.to_string());
+                writeln!(out, "{name}_bucket{} {cumulative}", render_labels(labels, Some(&le))).unwrap();
+            }
+            writeln!(out, "{name}_sum{} {}", render_labels(labels, None), histogram.sum).unwrap();
+            writeln!(out, "{name}_count{} {}", render_labels(labels, None), histogram.count).unwrap();
+        }
+
+        out
+    }
+}
+
+impl MetricsSink for InMemorySink {
+    fn increment_counter(&self, name: &'static str, labels: Labels<'_>, by: u64) {
+        *self.counters.lock().unwrap().entry((name, labels.to_vec())).or_default() += by;
+    }
+
+    fn record_histogram(&self, name: &'static str, labels: Labels<'_>, value: f64) {
+        let mut histograms = self.histograms.lock().unwrap();
+        let histogram = histograms.entry((name, labels.to_vec())).or_default();
+
+        let bucket = BUCKETS.iter().position(|&le| value <= le).unwrap_or(BUCKETS.len());
+        histogram.buckets[bucket] += 1;
+        histogram.sum += value;
+        histogram.count += 1;
+    }
+}
+
+fn render_labels(labels: &[(&'static str, &'static str)], le: Option<&str>) -> String {
+    let mut pairs: Vec<String> = labels
+        .iter()
+        .map(|(key, value)| format!("{key}=\"{}\"", escape_label(value)))
+        .collect();
+    if let Some(le) = le {
+        pairs.push(format!("le=\"{le}\""));
+    }
+
+    if pairs.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", pairs.join(","))
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(feature = "tokio")]
+/// Answers every connection on `listener` with the metrics of `sink` in the Prometheus text
+/// format, whatever was requested. Runs until accepting a connection fails.
+pub async fn serve_prometheus(listener: tokio::net::TcpListener, sink: Arc<InMemorySink>) -> std::io::Result<()> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+        let sink = sink.clone();
+
+        tokio::spawn(async move {
+            // The request itself doesn't matter, read it so the client isn't reset
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request).await;
+
+            let body = sink.render_prometheus();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            let _ = stream.write_all(response.as_bytes()).await;
+            let _ = stream.shutdown().await;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::comb::{join_futures, try_join};
+    use crate::executor::{block_on, yield_now};
+    use crate::metrics::*;
+
+    #[derive(Debug, PartialEq)]
+    enum LookupError {
+        NotFound,
+        TooSlow,
+    }
+
+    impl ErrorStatus for LookupError {
+        fn status(&self) -> Status {
+            match self {
+                LookupError::NotFound => Status::Error,
+                LookupError::TooSlow => Status::Timeout,
+            }
+        }
+    }
+
+    #[test]
+    fn metered_futures_report_latency_and_outcomes() {
+        let sink = Arc::new(InMemorySink::new());
+        let lookup = |res: Result<i32, LookupError>| async move {
+            yield_now().await;
+            res
+        };
+
+        with_sink(sink.clone(), || {
+            block_on(async {
+                assert_eq!(lookup(Ok(1)).metered("price").await, Ok(1));
+                assert_eq!(lookup(Err(LookupError::NotFound)).metered("price").await, Err(LookupError::NotFound));
+                assert_eq!(lookup(Err(LookupError::TooSlow)).metered("price").await, Err(LookupError::TooSlow));
+            })
+        });
+
+        let outcome = |status| sink.counter(OUTCOMES, &[("label", "price"), ("status", status)]);
+        assert_eq!(outcome("success"), 1);
+        assert_eq!(outcome("error"), 1);
+        assert_eq!(outcome("timeout"), 1);
+        assert_eq!(sink.histogram(LATENCY, &[("label", "price")]).unwrap().count, 3);
+
+        let text = sink.render_prometheus();
+        assert!(text.contains("# TYPE combinator_outcomes_total counter\n"));
+        assert!(text.contains("combinator_outcomes_total{label=\"price\",status=\"timeout\"} 1\n"));
+        assert!(text.contains("combinator_duration_seconds_bucket{label=\"price\",le=\"+Inf\"} 3\n"));
+    }
+
+    #[test]
+    fn combinators_report_their_branches() {
+        let sink = Arc::new(InMemorySink::new());
+        let lookup = |res: Result<i32, LookupError>| async move {
+            yield_now().await;
+            res
+        };
+
+        with_sink(sink.clone(), || {
+            block_on(async {
+                assert_eq!(join_futures(lookup(Ok(1)), lookup(Ok(2))).await, (Ok(1), Ok(2)));
+                assert_eq!(try_join(lookup(Ok(1)), lookup(Err(LookupError::NotFound))).await, Err(LookupError::NotFound));
+            })
+        });
+
+        let outcome = |combinator, status| sink.counter(BRANCH_OUTCOMES, &[("combinator", combinator), ("status", status)]);
+        assert_eq!(outcome("join", "success"), 2);
+        assert_eq!(outcome("try_join", "success"), 1);
+        assert_eq!(outcome("try_join", "error"), 1);
+        assert_eq!(sink.histogram(BRANCH_LATENCY, &[("combinator", "join")]).unwrap().count, 2);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn prometheus_endpoint_serves_the_metrics() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let sink = Arc::new(InMemorySink::new());
+        sink.increment_counter(RETRIES, &[("combinator", "hedge"), ("reason", "delay")], 2);
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(serve_prometheus(listener, sink));
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"GET /metrics HTTP/1.1\r\n\r\n").await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        server.abort();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("combinator_retries_total{combinator=\"hedge\",reason=\"delay\"} 2\n"));
+    }
+}
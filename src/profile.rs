@@ -0,0 +1,303 @@
+//! Measures how long each `poll` of a future takes, to find futures that block the executor.
+//!
+//! Poll durations are kept in a histogram per label, see [`poll_histograms`]. A poll taking
+//! longer than the threshold is also logged as a `tracing` warning when the `tracing` feature
+//! is enabled. The time spent in nested measured polls is left out of a poll's duration, so a
+//! future that blocks is only reported under its own label, not under every combinator above it.
+
+use std::{
+    cell::Cell,
+    collections::BTreeMap,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use crate::comb::{Describe, Kind};
+
+const BUCKETS: usize = 32;
+
+/// Distribution of poll durations, in power of two buckets of microseconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PollHistogram {
+    // Bucket `i` counts polls shorter than `2^i` µs, the last one everything longer
+    buckets: [u64; BUCKETS],
+    count: u64,
+    total: Duration,
+    max: Duration,
+    slow: u64,
+}
+
+impl PollHistogram {
+    const fn new() -> Self {
+        PollHistogram {
+            buckets: [0; BUCKETS],
+            count: 0,
+            total: Duration::ZERO,
+            max: Duration::ZERO,
+            slow: 0,
+        }
+    }
+
+    fn record(&mut self, elapsed: Duration, slow: bool) {
+        let micros = elapsed.as_micros() as u64;
+        let bucket = (u64::BITS - micros.leading_zeros()) as usize;
+        self.buckets[bucket.min(BUCKETS - 1)] += 1;
+        self.count += 1;
+        self.total += elapsed;
+        self.max = self.max.max(elapsed);
+        self.slow += slow as u64;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Polls that took longer than their threshold.
+    pub fn slow(&self) -> u64 {
+        self.slow
+    }
+
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    pub fn mean(&self) -> Duration {
+        match self.count {
+            0 => Duration::ZERO,
+            count => Duration::from_nanos((self.total.as_nanos() / u128::from(count)) as u64),
+        }
+    }
+
+    /// Upper bound of the bucket holding the `q` quantile, e.g. `0.99` for the p99.
+    pub fn quantile(&self, q: f64) -> Duration {
+        let rank = (q.clamp(0.0, 1.0) * self.count as f64).ceil() as u64;
+        let mut seen = 0;
+        for (upper, count) in self.buckets() {
+            seen += count;
+            if seen >= rank.max(1) {
+                return upper.min(self.max);
+            }
+        }
+        self.max
+    }
+
+    /// Upper bound and count of every bucket.
+    pub fn buckets(&self) -> impl Iterator<Item = (Duration, u64)> + '_ {
+        self.buckets
+            .iter()
+            .enumerate()
+            .map(|(i, &count)| (Duration::from_micros(1 << i), count))
+    }
+}
+
+static HISTOGRAMS: Mutex<BTreeMap<&'static str, PollHistogram>> = Mutex::new(BTreeMap::new());
+
+// Nanoseconds, zero when off
+static GLOBAL_THRESHOLD: AtomicU64 = AtomicU64::new(0);
+
+/// Snapshot of the poll histogram of every label measured so far.
+pub fn poll_histograms() -> BTreeMap<&'static str, PollHistogram> {
+    HISTOGRAMS.lock().unwrap().clone()
+}
+
+pub fn reset_poll_histograms() {
+    HISTOGRAMS.lock().unwrap().clear();
+}
+
+/// Measures every poll of the combinators as if they were wrapped in [`instrument_polls`]
+/// with `threshold`, under the name of their [`Kind`], e.g. `"join"`. Futures named with
+/// [`leaf`](crate::comb::leaf) or `traced` are recorded under their label. `None` turns it off.
+pub fn set_global_threshold(threshold: Option<Duration>) {
+    let nanos = threshold.map_or(0, |threshold| (threshold.as_nanos() as u64).max(1));
+    GLOBAL_THRESHOLD.store(nanos, Ordering::Relaxed);
+}
+
+pub fn global_threshold() -> Option<Duration> {
+    match GLOBAL_THRESHOLD.load(Ordering::Relaxed) {
+        0 => None,
+        nanos => Some(Duration::from_nanos(nanos)),
+    }
+}
+
+/// Records a poll of `label` that took `elapsed`, warning if it exceeded `threshold`.
+pub(crate) fn observe(label: &'static str, elapsed: Duration, threshold: Duration) {
+    let slow = elapsed > threshold;
+    HISTOGRAMS
+        .lock()
+        .unwrap()
+        .entry(label)
+        .or_insert_with(PollHistogram::new)
+        .record(elapsed, slow);
+
+    #[cfg(feature = "tracing")]
+    if slow {
+        tracing::warn!(label, ?elapsed, ?threshold, "poll of {label} blocked the executor");
+    }
+}
+
+thread_local! {
+    // Time spent in the measured polls nested in the one running on this thread
+    static NESTED: Cell<Duration> = const { Cell::new(Duration::ZERO) };
+}
+
+/// Times `poll`, reporting it with [`observe`] if profiling is on. Only the time spent in
+/// `poll` itself counts, nested calls report theirs under their own label.
+pub(crate) fn timed<T>(label: &'static str, threshold: Option<Duration>, poll: impl FnOnce() -> T) -> T {
+    let Some(threshold) = threshold else {
+        return poll();
+    };
+
+    let outer = NESTED.replace(Duration::ZERO);
+    let start = Instant::now();
+    let res = poll();
+    let elapsed = start.elapsed();
+    let nested = NESTED.replace(outer + elapsed);
+    observe(label, elapsed.saturating_sub(nested), threshold);
+    res
+}
+
+/// A future whose polls are timed, see [`instrument_polls`].
+pub struct InstrumentPolls<F> {
+    inner: F,
+    threshold: Duration,
+    label: &'static str,
+}
+
+/// Measures the wall time of every `poll` of `future`, recording it in the histogram of
+/// its label and warning when a single poll takes longer than `threshold`.
+pub fn instrument_polls<F: Future>(future: F, threshold: Duration) -> InstrumentPolls<F> {
+    InstrumentPolls {
+        inner: future,
+        threshold,
+        label: "unlabelled",
+    }
+}
+
+impl<F> InstrumentPolls<F> {
+    /// Sets the label the polls are recorded under.
+    pub fn label(mut self, label: &'static str) -> Self {
+        self.label = label;
+        self
+    }
+}
+
+impl<F: Future> Future for InstrumentPolls<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let inner = unsafe { Pin::new_unchecked(&mut this.inner) };
+        timed(this.label, Some(this.threshold), || inner.poll(cx))
+    }
+}
+
+impl<F: Describe> Describe for InstrumentPolls<F> {
+    fn kind(&self) -> Kind {
+        self.inner.kind()
+    }
+
+    fn label(&self) -> Option<&'static str> {
+        Some(self.label)
+    }
+
+    fn for_each_child(&self, visit: &mut dyn FnMut(&dyn Describe)) {
+        self.inner.for_each_child(visit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::PoisonError, thread};
+
+    use crate::comb::*;
+    use crate::executor::{block_on, yield_now};
+    use crate::profile::*;
+
+    // The global threshold is shared by every test
+    static GLOBAL: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn instrument_polls_records_slow_polls() {
+        let blocking = async {
+            thread::sleep(Duration::from_millis(5));
+            yield_now().await;
+        };
+
+        block_on(instrument_polls(blocking, Duration::from_millis(1)).label("blocking mapper"));
+
+        let histogram = &poll_histograms()["blocking mapper"];
+        assert_eq!(histogram.count(), 2);
+        assert_eq!(histogram.slow(), 1);
+        assert!(histogram.max() >= Duration::from_millis(5));
+        assert!(histogram.quantile(1.0) >= Duration::from_millis(5));
+    }
+
+    #[test]
+    fn global_threshold_times_combinators() {
+        let _global = GLOBAL.lock().unwrap_or_else(PoisonError::into_inner);
+        set_global_threshold(Some(Duration::from_secs(1)));
+        block_on(select(leaf(yield_now(), "global leaf"), std::future::pending::<()>()));
+        set_global_threshold(None);
+        block_on(leaf(yield_now(), "ignored leaf"));
+
+        let histograms = poll_histograms();
+        assert_eq!(histograms["global leaf"].count(), 2);
+        assert_eq!(histograms["global leaf"].slow(), 0);
+        assert!(histograms["select"].count() >= 2);
+        assert!(!histograms.contains_key("ignored leaf"));
+    }
+
+    #[test]
+    fn global_threshold_only_reports_the_blocking_future() {
+        let _global = GLOBAL.lock().unwrap_or_else(PoisonError::into_inner);
+        let blocking = async {
+            thread::sleep(Duration::from_millis(5));
+        };
+        let pipeline = join_futures(
+            sequential(leaf(blocking, "nested blocking"), |()| leaf(yield_now(), "nested fast")),
+            leaf(yield_now(), "nested sibling"),
+        );
+
+        set_global_threshold(Some(Duration::from_millis(2)));
+        block_on(leaf(map(leaf(pipeline, "nested inner"), |_| ()), "nested outer"));
+        set_global_threshold(None);
+
+        let histograms = poll_histograms();
+        assert_eq!(histograms["nested blocking"].slow(), 1);
+        for label in ["nested fast", "nested sibling", "nested inner", "nested outer"] {
+            assert_eq!(histograms[label].slow(), 0, "{label}");
+        }
+    }
+
+    #[test]
+    fn mean_divides_the_total_in_nanoseconds() {
+        let mut histogram = PollHistogram::new();
+        assert_eq!(histogram.mean(), Duration::ZERO);
+        histogram.record(Duration::from_nanos(1), false);
+        histogram.record(Duration::from_nanos(2), false);
+        assert_eq!(histogram.mean(), Duration::from_nanos(1));
+        histogram.record(Duration::from_nanos(6), false);
+        assert_eq!(histogram.mean(), Duration::from_nanos(3));
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn global_threshold_times_traced_combinators() {
+        let _global = GLOBAL.lock().unwrap_or_else(PoisonError::into_inner);
+        set_global_threshold(Some(Duration::from_secs(1)));
+        block_on(map(yield_now(), |()| 1).traced("global map"));
+        set_global_threshold(None);
+        block_on(map(yield_now(), |()| 1).traced("ignored map"));
+
+        let histograms = poll_histograms();
+        assert_eq!(histograms["global map"].count(), 2);
+        assert_eq!(histograms["global map"].slow(), 0);
+        assert!(!histograms.contains_key("ignored map"));
+    }
+}
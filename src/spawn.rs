@@ -0,0 +1,718 @@
+//! Combinators that spawn their branches onto the tokio runtime, so they can make progress
+//! on several worker threads instead of sharing the task of the combinator that owns them.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, PoisonError, Weak},
+    task::{Context, Poll},
+};
+
+use tokio::{sync::oneshot, task::JoinSet};
+
+use crate::comb::*;
+
+struct ScopeState {
+    tasks: JoinSet<()>,
+    closed: bool,
+    next_id: u64,
+    // Panics of children, by task id. Taken by the child's `ScopedTask` when it is awaited,
+    // the first one left is resumed when the scope closes
+    panics: Vec<(u64, PanicPayload)>,
+}
+
+impl ScopeState {
+    fn take_panic(&mut self, id: u64) -> Option<PanicPayload> {
+        let index = self.panics.iter().position(|(other, _)| *other == id)?;
+        Some(self.panics.remove(index).1)
+    }
+}
+
+/// Handle used to spawn children inside a [`scope`]. Clones spawn into the same scope.
+#[derive(Clone)]
+pub struct Scope {
+    state: Arc<Mutex<ScopeState>>,
+}
+
+/// Aborts the children when the scope is dropped before returning. Children may hold a
+/// [`Scope`] themselves, so the state can't be relied on to be dropped with the scope.
+struct AbortOnDrop(Arc<Mutex<ScopeState>>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        let mut state = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        state.closed = true;
+        state.tasks.abort_all();
+    }
+}
+
+/// Runs `body`, handing it a [`Scope`] to spawn tasks onto the runtime.
+///
+/// The scope only returns once every child spawned in it has finished. If a child panics,
+/// the panic is raised again where its [`ScopedTask`] is awaited, or when the scope returns
+/// if the handle was dropped. If the scope itself is dropped or panics, its children are aborted.
+///
+/// `body` is only called once the scope is first polled, so it shows up in [`Describe`] trees
+/// from then on.
+pub fn scope<B, Fut>(body: B) -> Scoped<B, Fut>
+where
+    B: FnOnce(Scope) -> Fut,
+    Fut: Future,
+{
+    let scope = Scope {
+        state: Arc::new(Mutex::new(ScopeState {
+            tasks: JoinSet::new(),
+            closed: false,
+            next_id: 0,
+            panics: Vec::new(),
+        })),
+    };
+
+    Scoped {
+        stage: ScopeStage::Start(body),
+        // Dropping the scope (being cancelled, or the body panicking) runs the guard, which
+        // aborts every child still in the scope. The ones being drained are aborted by
+        // dropping their `JoinSet`
+        guard: AbortOnDrop(scope.state.clone()),
+        scope,
+    }
+}
+
+/// A running [`scope`].
+pub struct Scoped<B, Fut: Future> {
+    stage: ScopeStage<B, Fut>,
+    scope: Scope,
+    guard: AbortOnDrop,
+}
+
+enum ScopeStage<B, Fut: Future> {
+    // The body is only called once the scope is first polled
+    Start(B),
+    Body(Fut),
+    Draining { result: Option<Fut::Output>, tasks: JoinSet<()> },
+    Done,
+}
+
+impl<B, Fut> Future for Scoped<B, Fut>
+where
+    B: FnOnce(Scope) -> Fut,
+    Fut: Future,
+{
+    type Output = Fut::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Only the body future is pinned, and it is dropped in place once done
+        let this = unsafe { self.get_unchecked_mut() };
+
+        loop {
+            match &mut this.stage {
+                ScopeStage::Start(_) => {
+                    let ScopeStage::Start(body) = std::mem::replace(&mut this.stage, ScopeStage::Done) else {
+                        unreachable!()
+                    };
+                    this.stage = ScopeStage::Body(body(this.scope.clone()));
+                }
+                ScopeStage::Body(body) => {
+                    let Poll::Ready(result) = unsafe { Pin::new_unchecked(body) }.poll(cx) else {
+                        return Poll::Pending;
+                    };
+                    this.stage = ScopeStage::Draining {
+                        result: Some(result),
+                        tasks: JoinSet::new(),
+                    };
+                }
+                ScopeStage::Draining { result, tasks } => {
+                    while let Poll::Ready(Some(res)) = tasks.poll_join_next(cx) {
+                        // Children catch their own panics, so this can only fail if the runtime
+                        // is shutting down
+                        if let Err(e) = res {
+                            panic!("scoped task failed: {e}");
+                        }
+                    }
+                    if !tasks.is_empty() {
+                        return Poll::Pending;
+                    }
+
+                    // Children may spawn grandchildren into the scope while we wait, so drain
+                    // in rounds
+                    let mut state = this.guard.0.lock().unwrap();
+                    if !state.tasks.is_empty() {
+                        *tasks = std::mem::take(&mut state.tasks);
+                        continue;
+                    }
+
+                    state.closed = true;
+                    let panic = (!state.panics.is_empty()).then(|| state.panics.remove(0).1);
+                    drop(state);
+                    if let Some(panic) = panic {
+                        panic.resume();
+                    }
+
+                    let result = result.take().unwrap();
+                    this.stage = ScopeStage::Done;
+                    return Poll::Ready(result);
+                }
+                ScopeStage::Done => panic!("Scoped polled after completion"),
+            }
+        }
+    }
+}
+
+impl<B, Fut> Describe for Scoped<B, Fut>
+where
+    Fut: Future + Describe,
+{
+    fn kind(&self) -> Kind {
+        Kind::Scope
+    }
+
+    fn for_each_child(&self, visit: &mut dyn FnMut(&dyn Describe)) {
+        if let ScopeStage::Body(body) = &self.stage {
+            visit(body);
+        }
+    }
+}
+
+impl Scope {
+    pub fn spawn<F>(&self, future: F) -> ScopedTask<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        let state = Arc::downgrade(&self.state);
+
+        let mut guard = self.state.lock().unwrap();
+        assert!(!guard.closed, "spawned into a scope that has already returned");
+        let id = guard.next_id;
+        guard.next_id += 1;
+
+        let task_state = state.clone();
+        guard.tasks.spawn(async move {
+            match catch_unwind(future).await {
+                Ok(res) => {
+                    let _ = tx.send(res);
+                }
+                Err(panic) => {
+                    // Stored before `tx` is dropped, so the handle finds it once it sees the
+                    // channel close, and it is kept if the handle is gone
+                    if let Some(state) = task_state.upgrade() {
+                        state.lock().unwrap().panics.push((id, panic));
+                    }
+                    drop(tx);
+                }
+            }
+        });
+
+        ScopedTask { rx, id, state }
+    }
+
+    /// Spawns every future and collects their outputs in order, like [`join_all`].
+    pub fn spawn_all<I>(&self, iter: I) -> JoinAll<ScopedTask<<I::Item as Future>::Output>>
+    where
+        I: IntoIterator,
+        I::Item: Future + Send + 'static,
+        <I::Item as Future>::Output: Send + 'static,
+    {
+        let tasks: Vec<_> = iter.into_iter().map(|future| self.spawn(future)).collect();
+        join_all(tasks)
+    }
+}
+
+/// Output of a child spawned in a [`scope`]. Awaiting it re-raises the child's panic.
+pub struct ScopedTask<T> {
+    rx: oneshot::Receiver<T>,
+    id: u64,
+    // Weak so that a handle moved into another child doesn't keep the scope alive
+    state: Weak<Mutex<ScopeState>>,
+}
+
+impl<T> Future for ScopedTask<T> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match Pin::new(&mut self.rx).poll(cx) {
+            Poll::Ready(Ok(res)) => Poll::Ready(res),
+            Poll::Ready(Err(_)) => {
+                let panic = self
+                    .state
+                    .upgrade()
+                    .and_then(|state| state.lock().unwrap().take_panic(self.id));
+                match panic {
+                    Some(panic) => panic.resume(),
+                    None => panic!("scoped task was aborted before completing"),
+                }
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<T> Describe for ScopedTask<T> {
+    fn kind(&self) -> Kind {
+        Kind::Spawned
+    }
+}
+
+#[derive(Debug)]
+pub enum SpawnError {
+    /// The spawned branch panicked.
+    Panicked(PanicPayload),
+    /// The spawned branch was cancelled, e.g. because the runtime is shutting down.
+    Cancelled,
+}
+
+impl From<tokio::task::JoinError> for SpawnError {
+    fn from(err: tokio::task::JoinError) -> Self {
+        match err.try_into_panic() {
+            Ok(payload) => SpawnError::Panicked(PanicPayload::new(payload)),
+            Err(_) => SpawnError::Cancelled,
+        }
+    }
+}
+
+impl std::fmt::Display for SpawnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SpawnError::Panicked(panic) => write!(f, "spawned branch failed: {panic}"),
+            SpawnError::Cancelled => write!(f, "spawned branch was cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for SpawnError {}
+
+/// A branch running on the runtime. The task is aborted if this is dropped before it completes.
+pub struct SpawnedTask<T> {
+    handle: tokio::task::JoinHandle<T>,
+}
+
+impl<T> Future for SpawnedTask<T> {
+    type Output = Result<T, SpawnError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.handle).poll(cx).map_err(SpawnError::from)
+    }
+}
+
+impl<T> Drop for SpawnedTask<T> {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+impl<T> Describe for SpawnedTask<T> {
+    fn kind(&self) -> Kind {
+        Kind::Spawned
+    }
+}
+
+fn spawn_branch<F>(future: F) -> SpawnedTask<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    SpawnedTask {
+        handle: tokio::spawn(future),
+    }
+}
+
+/// Branches spawned with [`join_spawned`], by output type.
+pub type JoinSpawned<A, B> = TryJoin<SpawnedTask<A>, SpawnedTask<B>, A, B, SpawnError>;
+
+/// Like [`join_futures`], but each branch is spawned onto the runtime so they run in parallel.
+///
+/// The branches start running as soon as this is called. If either fails, the join resolves
+/// with its error and the other branch is aborted, as is every branch still running when
+/// the returned future is dropped.
+pub fn join_spawned<A, B>(a: A, b: B) -> JoinSpawned<A::Output, B::Output>
+where
+    A: Future + Send + 'static,
+    B: Future + Send + 'static,
+    A::Output: Send + 'static,
+    B::Output: Send + 'static,
+{
+    try_join(spawn_branch(a), spawn_branch(b))
+}
+
+/// Branches spawned with [`join_all_spawned`].
+pub struct JoinAllSpawned<T> {
+    // Taken out once the branch finished
+    tasks: Vec<Option<SpawnedTask<T>>>,
+    outputs: Vec<Option<T>>,
+    pending: usize,
+    done: bool,
+    order: PollOrder,
+}
+
+/// Spawns every future onto the runtime and collects their outputs in order, like [`join_all`].
+///
+/// Resolves with the first error as soon as a branch fails, aborting the branches still
+/// running, like [`join_spawned`].
+pub fn join_all_spawned<I>(iter: I) -> JoinAllSpawned<<I::Item as Future>::Output>
+where
+    I: IntoIterator,
+    I::Item: Future + Send + 'static,
+    <I::Item as Future>::Output: Send + 'static,
+{
+    let tasks: Vec<_> = iter.into_iter().map(|future| Some(spawn_branch(future))).collect();
+    JoinAllSpawned {
+        outputs: tasks.iter().map(|_| None).collect(),
+        pending: tasks.len(),
+        tasks,
+        done: false,
+        order: PollOrder::default(),
+    }
+}
+
+impl<T> JoinAllSpawned<T> {
+    /// Sets the order the branches are polled in, and so which error is reported when several
+    /// fail at once.
+    pub fn policy(mut self, policy: PollPolicy) -> Self {
+        self.order = PollOrder::new(policy);
+        self
+    }
+}
+
+// Outputs are never pinned, and the tasks are join handles, which are `Unpin` themselves
+impl<T> Unpin for JoinAllSpawned<T> {}
+
+impl<T> Future for JoinAllSpawned<T> {
+    type Output = Result<Vec<T>, SpawnError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        if this.done {
+            panic!("JoinAllSpawned polled after completion");
+        }
+
+        let mut running: Vec<usize> = (0..this.tasks.len()).filter(|&i| this.tasks[i].is_some()).collect();
+        this.order.arrange(&mut running, this.tasks.len());
+
+        for index in running {
+            let Some(task) = &mut this.tasks[index] else {
+                continue;
+            };
+
+            match Pin::new(task).poll(cx) {
+                Poll::Ready(Ok(res)) => {
+                    this.outputs[index] = Some(res);
+                    this.tasks[index] = None;
+                    this.pending -= 1;
+                }
+                Poll::Ready(Err(e)) => {
+                    // Dropping the handles aborts the other branches
+                    this.done = true;
+                    this.tasks.clear();
+                    return Poll::Ready(Err(e));
+                }
+                Poll::Pending => {}
+            }
+        }
+
+        if this.pending > 0 {
+            return Poll::Pending;
+        }
+
+        this.done = true;
+        let outputs = std::mem::take(&mut this.outputs);
+        Poll::Ready(Ok(outputs.into_iter().map(Option::unwrap).collect()))
+    }
+}
+
+impl<T> Describe for JoinAllSpawned<T> {
+    fn kind(&self) -> Kind {
+        Kind::JoinAll
+    }
+
+    fn for_each_child(&self, visit: &mut dyn FnMut(&dyn Describe)) {
+        for task in self.tasks.iter().flatten() {
+            visit(task);
+        }
+    }
+}
+
+fn resume_blocking<T>(res: Result<T, SpawnError>) -> T {
+    match res {
+        Ok(res) => res,
+        Err(SpawnError::Panicked(panic)) => panic.resume(),
+        Err(SpawnError::Cancelled) => panic!("blocking pool shut down before the mapper ran"),
+    }
+}
+
+/// A future whose output is mapped on the blocking thread pool, see [`map_blocking`].
+pub struct MapBlocking<F, M, U> {
+    state: MapBlockingState<F, M, U>,
+}
+
+enum MapBlockingState<F, M, U> {
+    Polling { future: F, mapper: M },
+    Mapping(SpawnedTask<U>),
+    Done,
+}
+
+/// Like [`map`], but the mapper runs on tokio's blocking thread pool instead of inside `poll`,
+/// so an expensive transform does not stall the executor. A panic in the mapper is re-raised
+/// here.
+pub fn map_blocking<F, M, U>(task: F, mapper: M) -> MapBlocking<F, M, U>
+where
+    F: Future,
+    F::Output: Send + 'static,
+    M: FnOnce(F::Output) -> U + Send + 'static,
+    U: Send + 'static,
+{
+    MapBlocking {
+        state: MapBlockingState::Polling { future: task, mapper },
+    }
+}
+
+impl<F, M, U> Future for MapBlocking<F, M, U>
+where
+    F: Future,
+    F::Output: Send + 'static,
+    M: FnOnce(F::Output) -> U + Send + 'static,
+    U: Send + 'static,
+{
+    type Output = U;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Only `future` is pinned, and it is dropped in place once it completed
+        let this = unsafe { &mut self.get_unchecked_mut().state };
+
+        loop {
+            match this {
+                MapBlockingState::Polling { future, .. } => {
+                    let Poll::Ready(res) = unsafe { Pin::new_unchecked(future) }.poll(cx) else {
+                        return Poll::Pending;
+                    };
+                    let MapBlockingState::Polling { mapper, .. } = std::mem::replace(this, MapBlockingState::Done) else {
+                        unreachable!()
+                    };
+                    *this = MapBlockingState::Mapping(SpawnedTask {
+                        handle: tokio::task::spawn_blocking(move || mapper(res)),
+                    });
+                }
+                MapBlockingState::Mapping(task) => {
+                    let res = match Pin::new(task).poll(cx) {
+                        Poll::Ready(res) => res,
+                        Poll::Pending => return Poll::Pending,
+                    };
+                    *this = MapBlockingState::Done;
+                    return Poll::Ready(resume_blocking(res));
+                }
+                MapBlockingState::Done => panic!("MapBlocking polled after completion"),
+            }
+        }
+    }
+}
+
+impl<F, M, U> Describe for MapBlocking<F, M, U>
+where
+    F: Describe,
+{
+    fn kind(&self) -> Kind {
+        Kind::Map
+    }
+
+    fn for_each_child(&self, visit: &mut dyn FnMut(&dyn Describe)) {
+        match &self.state {
+            MapBlockingState::Polling { future, .. } => visit(future),
+            MapBlockingState::Mapping(task) => visit(task),
+            MapBlockingState::Done => {}
+        }
+    }
+}
+
+/// Branches of [`bimap_blocking`].
+pub type BimapBlocking<A, B, F, G, AR2, BR2> = JoinFutures<MapBlocking<A, F, AR2>, MapBlocking<B, G, BR2>, AR2, BR2>;
+
+/// Like [`join_futures_bimap`], with both mappers running on the blocking thread pool.
+pub fn bimap_blocking<A, B, AR2, BR2, F, G>(a: A, b: B, f: F, g: G) -> BimapBlocking<A, B, F, G, AR2, BR2>
+where
+    A: Future,
+    B: Future,
+    A::Output: Send + 'static,
+    B::Output: Send + 'static,
+    F: FnOnce(A::Output) -> AR2 + Send + 'static,
+    G: FnOnce(B::Output) -> BR2 + Send + 'static,
+    AR2: Send + 'static,
+    BR2: Send + 'static,
+{
+    join_futures(map_blocking(a, f), map_blocking(b, g))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use crate::comb::*;
+    use crate::spawn::*;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn scope_waits_for_every_child() {
+        let finished = Arc::new(AtomicUsize::new(0));
+
+        let (a, b) = scope(|s| {
+            let finished = finished.clone();
+            async move {
+                // Never awaited, the scope still waits for it
+                s.spawn({
+                    let finished = finished.clone();
+                    async move {
+                        tokio::time::sleep(Duration::from_millis(20)).await;
+                        finished.fetch_add(1, Ordering::SeqCst);
+                    }
+                });
+
+                join_futures(s.spawn(async { 1 }), s.spawn(async { 2 })).await
+            }
+        })
+        .await;
+
+        assert_eq!((a, b), (1, 2));
+        assert_eq!(finished.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn scope_propagates_child_panics() {
+        let result = catch_unwind(scope(|s| async move {
+            let (open, gate) = tokio::sync::oneshot::channel::<()>();
+            let child = s.spawn(async move {
+                let _ = gate.await;
+                panic!("price service is down")
+            });
+
+            // The handle is gone before the child gets to panic
+            drop(child);
+            open.send(()).unwrap();
+        }))
+        .await;
+
+        assert_eq!(result.unwrap_err().message(), Some("price service is down"));
+    }
+
+    #[tokio::test]
+    async fn scope_keeps_panics_of_finished_children() {
+        let result = catch_unwind(scope(|s| async move {
+            let child = s.spawn(async { panic!("no stock for product 3") });
+
+            while s.state.lock().unwrap().panics.is_empty() {
+                tokio::task::yield_now().await;
+            }
+            drop(child);
+        }))
+        .await;
+
+        assert_eq!(result.unwrap_err().message(), Some("no stock for product 3"));
+    }
+
+    #[tokio::test]
+    async fn dropping_the_scope_aborts_children_holding_it() {
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+
+        let running = scope(|s| async move {
+            let inner = s.clone();
+            s.spawn(async move {
+                // Keeps the scope's state alive for as long as the task is
+                let (_inner, _tx) = (inner, tx);
+                std::future::pending::<()>().await
+            });
+            std::future::pending::<()>().await
+        });
+        let mut running = Box::pin(running);
+        let polled = std::future::poll_fn(|cx| Poll::Ready(running.as_mut().poll(cx))).await;
+        assert!(polled.is_pending());
+        drop(running);
+
+        assert!(rx.await.is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn join_spawned_reports_panics_as_errors() {
+        let res = join_spawned(async { 12.41 }, async { panic!("no product with id 3") }).await;
+
+        match res {
+            Err(SpawnError::Panicked(panic)) => assert_eq!(panic.message(), Some("no product with id 3")),
+            other => panic!("expected a panic, got {other:?}"),
+        }
+
+        let prices = join_all_spawned((1..=3).map(|id| async move { id * 10 })).await.unwrap();
+        assert_eq!(prices, vec![10, 20, 30]);
+    }
+
+    #[tokio::test]
+    async fn dropping_join_spawned_aborts_the_branches() {
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+
+        let join = join_spawned(
+            async move {
+                // The sender is dropped once the task is aborted
+                let _tx = tx;
+                std::future::pending::<()>().await
+            },
+            async {},
+        );
+        drop(join);
+
+        assert!(rx.await.is_err());
+    }
+
+    #[tokio::test]
+    async fn join_all_spawned_fails_fast_and_aborts_the_rest() {
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let stuck = async move {
+            // The sender is dropped once the task is aborted
+            let _tx = tx;
+            std::future::pending::<i32>().await
+        };
+        let failing = async { panic!("no product with id 3") };
+
+        let branches = [Box::pin(stuck) as Pin<Box<dyn Future<Output = i32> + Send>>, Box::pin(failing)];
+        let res = join_all_spawned(branches).await;
+
+        match res {
+            Err(SpawnError::Panicked(panic)) => assert_eq!(panic.message(), Some("no product with id 3")),
+            other => panic!("expected a panic, got {other:?}"),
+        }
+        assert!(rx.await.is_err());
+    }
+
+    #[tokio::test]
+    async fn spawned_combinators_can_be_described() {
+        let prices = join_spawned(async { 12.41 }, async { 3.5 }).policy(PollPolicy::RoundRobin);
+        assert_eq!(describe_tree(&prices), "try_join\n  spawned\n  spawned\n");
+        assert_eq!(prices.await.unwrap(), (12.41, 3.5));
+
+        let stock = join_all_spawned((1..=2).map(|id| async move { id })).policy(PollPolicy::Random { seed: 7 });
+        let totals = bimap_blocking(leaf(async { 12.41 }, "price"), stock, |price| price * 2.0, Result::unwrap);
+        assert_eq!(
+            describe_tree(&totals),
+            "join\n  map\n    leaf \"price\"\n  map\n    join_all\n      spawned\n      spawned\n"
+        );
+        assert_eq!(totals.await, (24.82, vec![1, 2]));
+
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let body = |s: Scope| leaf(async move { rx.await.map(|()| s.spawn(async { 1 })).unwrap().await }, "body");
+        let mut scoped = Box::pin(scope(body));
+        assert_eq!(describe_tree(&*scoped), "scope\n");
+        let polled = std::future::poll_fn(|cx| Poll::Ready(scoped.as_mut().poll(cx))).await;
+        assert!(polled.is_pending());
+        assert_eq!(describe_tree(&*scoped), "scope\n  leaf \"body\"\n");
+        tx.send(()).unwrap();
+        assert_eq!(scoped.await, 1);
+    }
+
+    #[tokio::test]
+    async fn blocking_mappers_run_off_the_executor() {
+        let executor = std::thread::current().id();
+        let on_pool = move |price: f32| (std::thread::current().id() != executor, price * 2.0);
+
+        let (a, b) = bimap_blocking(async { 1.5 }, async { 4.0 }, on_pool, on_pool).await;
+
+        assert_eq!(a, (true, 3.0));
+        assert_eq!(b, (true, 8.0));
+    }
+}
@@ -0,0 +1,716 @@
+//! Records when labelled combinator nodes start, get polled and finish, and exports the
+//! timeline as a Chrome trace (`chrome://tracing`, Perfetto) or a standalone HTML Gantt chart.
+
+use std::{
+    cell::Cell,
+    collections::{HashMap, HashSet},
+    fmt::Write,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use crate::comb::{Describe, Kind};
+
+thread_local! {
+    // Recorder and id of the node being polled on this thread, parent of any node first polled inside it
+    static CURRENT: Cell<Option<(usize, usize)>> = const { Cell::new(None) };
+}
+
+/// One poll of a recorded node, relative to the recorder's creation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PollSpan {
+    pub start: Duration,
+    pub end: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeRecord {
+    pub id: usize,
+    pub label: &'static str,
+    /// The recorded node that was being polled when this one was first polled.
+    pub parent: Option<usize>,
+    /// First poll.
+    pub start: Option<Duration>,
+    /// Completion, or the moment the node was dropped if `cancelled`.
+    pub finish: Option<Duration>,
+    pub cancelled: bool,
+    pub polls: Vec<PollSpan>,
+}
+
+struct RecorderInner {
+    origin: Instant,
+    nodes: Mutex<Vec<NodeRecord>>,
+}
+
+/// Collects the timeline of every future wrapped with [`Recordable::recorded`].
+/// Clones share the same timeline.
+#[derive(Clone)]
+pub struct Recorder {
+    inner: Arc<RecorderInner>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Recorder {
+            inner: Arc::new(RecorderInner {
+                origin: Instant::now(),
+                nodes: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    fn key(&self) -> usize {
+        Arc::as_ptr(&self.inner) as usize
+    }
+
+    fn now(&self) -> Duration {
+        self.inner.origin.elapsed()
+    }
+
+    fn register(&self, label: &'static str) -> usize {
+        let mut nodes = self.inner.nodes.lock().unwrap();
+        let id = nodes.len();
+        nodes.push(NodeRecord {
+            id,
+            label,
+            parent: None,
+            start: None,
+            finish: None,
+            cancelled: false,
+            polls: Vec::new(),
+        });
+        id
+    }
+
+    fn update(&self, id: usize, f: impl FnOnce(&mut NodeRecord)) {
+        f(&mut self.inner.nodes.lock().unwrap()[id]);
+    }
+
+    /// Snapshot of the nodes recorded so far, in the order they were created.
+    pub fn nodes(&self) -> Vec<NodeRecord> {
+        self.inner.nodes.lock().unwrap().clone()
+    }
+
+    /// The timeline in the Chrome trace event format. Each node gets its own row, with its
+    /// lifetime as one slice and every poll as a slice nested inside it.
+    pub fn chrome_trace(&self) -> String {
+        let mut events = Vec::new();
+        for node in self.nodes() {
+            let Some(start) = node.start else { continue };
+            let finish = node.finish.unwrap_or_else(|| self.now());
+
+            let mut args = format!(
+                "\"polls\":{},\"cancelled\":{}",
+                node.polls.len(),
+                node.cancelled
+            );
+            if let Some(parent) = node.parent {
+                write!(args, ",\"parent\":{parent}").unwrap();
+            }
+            events.push(format!(
+                "{{\"name\":\"{}\",\"cat\":\"combinator\",\"ph\":\"X\",\"ts\":{},\"dur\":{},\"pid\":1,\"tid\":{},\"args\":{{{args}}}}}",
+                escape_json(node.label),
+                micros(start),
+                micros(finish - start),
+                node.id,
+            ));
+            events.push(format!(
+                "{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":{},\"args\":{{\"name\":\"{}\"}}}}",
+                node.id,
+                escape_json(node.label),
+            ));
+
+            for poll in &node.polls {
+                events.push(format!(
+                    "{{\"name\":\"poll\",\"cat\":\"poll\",\"ph\":\"X\",\"ts\":{},\"dur\":{},\"pid\":1,\"tid\":{}}}",
+                    micros(poll.start),
+                    micros(poll.end - poll.start),
+                    node.id,
+                ));
+            }
+        }
+
+        format!(
+            "{{\"traceEvents\":[{}],\"displayTimeUnit\":\"ms\"}}",
+            events.join(",")
+        )
+    }
+
+    /// The critical path of everything recorded so far.
+    pub fn critical_path(&self) -> CriticalPath {
+        critical_path(&self.nodes())
+    }
+
+    /// A self-contained HTML page with an SVG Gantt chart of the timeline. Children are listed
+    /// under their parent; the light bar is the node's lifetime and the dark ticks its polls.
+    pub fn gantt_html(&self) -> String {
+        const ROW: usize = 24;
+        const LABELS: usize = 220;
+        const WIDTH: usize = 900;
+
+        let nodes = self.nodes();
+        let rows = tree_order(&nodes);
+        let end = nodes
+            .iter()
+            .filter_map(|node| node.finish.or(node.start))
+            .max()
+            .unwrap_or_default()
+            .max(Duration::from_micros(1));
+        let x = |t: Duration| LABELS as f64 + t.as_secs_f64() / end.as_secs_f64() * WIDTH as f64;
+
+        let height = (rows.len() + 1) * ROW + 10;
+        let mut svg = String::new();
+        writeln!(
+            svg,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{height}\" font-family=\"monospace\" font-size=\"12\">",
+            LABELS + WIDTH + 20
+        )
+        .unwrap();
+
+        for tick in 0..=4 {
+            let t = end.mul_f64(tick as f64 / 4.0);
+            writeln!(
+                svg,
+                "<line x1=\"{0:.1}\" y1=\"{ROW}\" x2=\"{0:.1}\" y2=\"{height}\" stroke=\"#ddd\"/><text x=\"{0:.1}\" y=\"16\" text-anchor=\"middle\">{1:.3?}</text>",
+                x(t),
+                t
+            )
+            .unwrap();
+        }
+
+        for (row, (index, depth)) in rows.into_iter().enumerate() {
+            let node = &nodes[index];
+            let y = (row + 1) * ROW;
+            writeln!(
+                svg,
+                "<text x=\"{}\" y=\"{}\">{}</text>",
+                4 + depth * 12,
+                y + 16,
+                escape_xml(node.label)
+            )
+            .unwrap();
+
+            let Some(start) = node.start else { continue };
+            let finish = node.finish.unwrap_or(end);
+            let fill = if node.cancelled { "#f4c7c3" } else { "#c6dafc" };
+            writeln!(
+                svg,
+                "<rect x=\"{:.1}\" y=\"{}\" width=\"{:.1}\" height=\"{}\" fill=\"{fill}\"><title>{} {:?} ({} polls{})</title></rect>",
+                x(start),
+                y + 4,
+                (x(finish) - x(start)).max(1.0),
+                ROW - 8,
+                escape_xml(node.label),
+                finish - start,
+                node.polls.len(),
+                if node.cancelled { ", cancelled" } else { "" },
+            )
+            .unwrap();
+
+            for poll in &node.polls {
+                writeln!(
+                    svg,
+                    "<rect x=\"{:.1}\" y=\"{}\" width=\"{:.1}\" height=\"{}\" fill=\"#1a73e8\"/>",
+                    x(poll.start),
+                    y + 4,
+                    (x(poll.end) - x(poll.start)).max(1.0),
+                    ROW - 8,
+                )
+                .unwrap();
+            }
+        }
+        svg.push_str("</svg>");
+
+        format!(
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Combinator timeline</title></head>\n<body>\n{svg}\n</body></html>\n"
+        )
+    }
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for Recorder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Recorder")
+            .field("nodes", &self.inner.nodes.lock().unwrap().len())
+            .finish()
+    }
+}
+
+/// Timing of one node in a [`CriticalPath`] report.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeTiming {
+    pub id: usize,
+    pub label: &'static str,
+    pub depth: usize,
+    pub duration: Duration,
+    /// How much later the node could have finished without delaying the whole run.
+    pub slack: Duration,
+    pub critical: bool,
+}
+
+/// The chain of nodes that determined the total latency of a recorded run, and the slack
+/// of every other node, see [`critical_path`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct CriticalPath {
+    pub total: Duration,
+    /// Ids of the nodes on the critical path, by start time.
+    pub path: Vec<usize>,
+    /// Every node that was started, parents before their children.
+    pub nodes: Vec<NodeTiming>,
+    /// Nodes dropped before completing, like the loser of a select, and the nodes under them.
+    /// They are left out of `path` and `nodes`.
+    pub cancelled: Vec<usize>,
+}
+
+/// Computes the critical path of a recorded run.
+///
+/// The recording only has timestamps, so dependencies are inferred from them: a node's
+/// latency is attributed to the child that finished last, then to the sibling that finished
+/// last before that child started (its predecessor in a sequence), and so on. A node that
+/// finished before a sibling started is assumed to gate that sibling when computing slack.
+/// Cancelled nodes didn't delay anything, so they are only listed in
+/// [`CriticalPath::cancelled`].
+///
+/// Nodes are matched to their parents by [`NodeRecord::id`], whatever their position in
+/// `nodes`. If several records share an id, only the first one is used.
+pub fn critical_path(nodes: &[NodeRecord]) -> CriticalPath {
+    let mut seen = HashSet::with_capacity(nodes.len());
+    let unique: Vec<NodeRecord> = nodes
+        .iter()
+        .filter(|node| seen.insert(node.id))
+        .cloned()
+        .collect();
+    let nodes = &unique[..];
+    let by_id: HashMap<usize, &NodeRecord> = nodes.iter().map(|node| (node.id, node)).collect();
+
+    let span = |node: &NodeRecord| Some((node.start?, node.finish?));
+    let children = |parent: Option<usize>| -> Vec<&NodeRecord> {
+        nodes
+            .iter()
+            .filter(|node| node.parent == parent && !node.cancelled && span(node).is_some())
+            .collect()
+    };
+
+    // Walks back from `until`, each step taking the node that finished last before it
+    fn walk<'a>(
+        candidates: Vec<&'a NodeRecord>,
+        mut until: Duration,
+        after: Duration,
+        children: &dyn Fn(Option<usize>) -> Vec<&'a NodeRecord>,
+        path: &mut Vec<usize>,
+    ) {
+        while let Some(node) = candidates
+            .iter()
+            .filter(|node| node.finish.unwrap() <= until && node.finish.unwrap() > after)
+            .max_by_key(|node| node.finish)
+        {
+            let (start, finish) = (node.start.unwrap(), node.finish.unwrap());
+            path.push(node.id);
+            walk(children(Some(node.id)), finish, start, children, path);
+            if start <= after {
+                break;
+            }
+            until = start;
+        }
+    }
+
+    let roots = children(None);
+    let total_start = roots
+        .iter()
+        .filter_map(|node| node.start)
+        .min()
+        .unwrap_or_default();
+    let total_end = roots
+        .iter()
+        .filter_map(|node| node.finish)
+        .max()
+        .unwrap_or_default();
+
+    let mut path = Vec::new();
+    walk(
+        roots.clone(),
+        total_end,
+        Duration::ZERO,
+        &children,
+        &mut path,
+    );
+    path.sort_by_key(|id| by_id[id].start);
+
+    // Latest each node may finish, parents before children, later siblings before earlier ones
+    let mut latest_finish: HashMap<usize, Duration> = HashMap::with_capacity(nodes.len());
+    let mut order: Vec<(Option<usize>, Duration)> = vec![(None, total_end)];
+    while let Some((parent, parent_latest)) = order.pop() {
+        let mut siblings = children(parent);
+        siblings.sort_by_key(|node| core::cmp::Reverse(node.start));
+
+        // The parent kept running after its last child finished, e.g. to merge the outputs
+        let last_child = siblings.iter().filter_map(|node| node.finish).max();
+        let tail = parent
+            .and_then(|id| by_id[&id].finish)
+            .zip(last_child)
+            .map_or(Duration::ZERO, |(finish, last_child)| {
+                finish.saturating_sub(last_child)
+            });
+        let parent_latest = parent_latest.saturating_sub(tail);
+
+        for (i, node) in siblings.iter().enumerate() {
+            let finish = node.finish.unwrap();
+            let latest = siblings[..i]
+                .iter()
+                .filter(|later| later.start.unwrap() >= finish)
+                .map(|later| {
+                    later.start.unwrap() + (latest_finish[&later.id] - later.finish.unwrap())
+                })
+                .fold(parent_latest, Duration::min);
+
+            let latest = latest.max(finish);
+            latest_finish.insert(node.id, latest);
+            order.push((Some(node.id), latest));
+        }
+    }
+
+    let mut cancelled = Vec::new();
+    let timings = tree_order(nodes)
+        .into_iter()
+        .filter_map(|(index, depth)| {
+            let node = &nodes[index];
+            if node.cancelled
+                || node
+                    .parent
+                    .is_some_and(|parent| cancelled.contains(&parent))
+            {
+                cancelled.push(node.id);
+                return None;
+            }
+            let (start, finish) = span(node)?;
+            Some(NodeTiming {
+                id: node.id,
+                label: node.label,
+                depth,
+                duration: finish - start,
+                // Unset for nodes whose parent never ran
+                slack: latest_finish
+                    .get(&node.id)
+                    .map_or(Duration::ZERO, |latest| *latest - finish),
+                critical: path.contains(&node.id),
+            })
+        })
+        .collect();
+
+    CriticalPath {
+        total: total_end - total_start,
+        path,
+        nodes: timings,
+        cancelled,
+    }
+}
+
+impl std::fmt::Display for CriticalPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "total latency {:?}, critical path marked with *",
+            self.total
+        )?;
+        for node in &self.nodes {
+            let label = format!("{}{}", "  ".repeat(node.depth), node.label);
+            writeln!(
+                f,
+                "{} {label:<32} {:>12?}  slack {:>12?}",
+                if node.critical { '*' } else { ' ' },
+                node.duration,
+                node.slack,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Node indices depth first, children after their parent in creation order, with their depth.
+/// Ids must be unique.
+fn tree_order(nodes: &[NodeRecord]) -> Vec<(usize, usize)> {
+    fn visit(nodes: &[NodeRecord], index: usize, depth: usize, out: &mut Vec<(usize, usize)>) {
+        out.push((index, depth));
+        let id = nodes[index].id;
+        for (child, _) in nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| node.parent == Some(id))
+        {
+            visit(nodes, child, depth + 1, out);
+        }
+    }
+
+    let mut out = Vec::with_capacity(nodes.len());
+    for (root, _) in nodes
+        .iter()
+        .enumerate()
+        .filter(|(_, node)| node.parent.is_none())
+    {
+        visit(nodes, root, 0, &mut out);
+    }
+    out
+}
+
+fn micros(duration: Duration) -> u128 {
+    duration.as_micros()
+}
+
+fn escape_json(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// A future whose polls are recorded as a node of a [`Recorder`]'s timeline.
+pub struct Recorded<F> {
+    inner: F,
+    recorder: Recorder,
+    id: usize,
+    label: &'static str,
+    started: bool,
+    done: bool,
+}
+
+pub trait Recordable: Future + Sized {
+    /// Records the future as a node named `label` on `recorder`.
+    fn recorded(self, recorder: &Recorder, label: &'static str) -> Recorded<Self> {
+        Recorded {
+            id: recorder.register(label),
+            inner: self,
+            recorder: recorder.clone(),
+            label,
+            started: false,
+            done: false,
+        }
+    }
+}
+
+impl<F: Future> Recordable for F {}
+
+impl<F> Future for Recorded<F>
+where
+    F: Future,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let (recorder, id) = (&this.recorder, this.id);
+        let key = recorder.key();
+
+        let start = recorder.now();
+        let outer = CURRENT.with(|current| current.replace(Some((key, id))));
+        if !std::mem::replace(&mut this.started, true) {
+            let parent = match outer {
+                Some((outer_key, parent)) if outer_key == key => Some(parent),
+                _ => None,
+            };
+            recorder.update(id, |node| {
+                node.parent = parent;
+                node.start = Some(start);
+            });
+        }
+
+        // Restored even if the inner future panics
+        struct Restore(Option<(usize, usize)>);
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                CURRENT.with(|current| current.set(self.0));
+            }
+        }
+        let restore = Restore(outer);
+
+        let res = unsafe { Pin::new_unchecked(&mut this.inner) }.poll(cx);
+        drop(restore);
+
+        let end = recorder.now();
+        this.done = res.is_ready();
+        recorder.update(id, |node| {
+            node.polls.push(PollSpan { start, end });
+            if res.is_ready() {
+                node.finish = Some(end);
+            }
+        });
+        res
+    }
+}
+
+impl<F> Describe for Recorded<F>
+where
+    F: Describe,
+{
+    fn kind(&self) -> Kind {
+        self.inner.kind()
+    }
+
+    fn label(&self) -> Option<&'static str> {
+        Some(self.label)
+    }
+
+    fn for_each_child(&self, visit: &mut dyn FnMut(&dyn Describe)) {
+        self.inner.for_each_child(visit)
+    }
+}
+
+impl<F> Drop for Recorded<F> {
+    fn drop(&mut self) {
+        if self.started && !self.done {
+            let now = self.recorder.now();
+            self.recorder.update(self.id, |node| {
+                node.finish = Some(now);
+                node.cancelled = true;
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::comb::*;
+    use crate::executor::{block_on, yield_now};
+    use crate::timeline::*;
+
+    #[test]
+    fn records_nested_nodes_and_exports_them() {
+        let recorder = Recorder::new();
+
+        let slow = async {
+            yield_now().await;
+            yield_now().await;
+        };
+        let pipeline = join_futures(
+            slow.recorded(&recorder, "slow"),
+            ready(()).recorded(&recorder, "fast"),
+        );
+        let abandoned = select(
+            std::future::pending::<()>().recorded(&recorder, "abandoned"),
+            ready(()),
+        );
+
+        block_on(join_futures(
+            pipeline.recorded(&recorder, "pipeline"),
+            abandoned,
+        ));
+
+        let nodes = recorder.nodes();
+        let node = |label| nodes.iter().find(|node| node.label == label).unwrap();
+
+        assert_eq!(node("slow").parent, Some(node("pipeline").id));
+        assert_eq!(node("fast").parent, Some(node("pipeline").id));
+        assert_eq!(node("pipeline").parent, None);
+        assert_eq!(node("slow").polls.len(), 3);
+        assert_eq!(node("fast").polls.len(), 1);
+        assert!(node("abandoned").cancelled);
+        assert!(node("slow").finish <= node("pipeline").finish);
+
+        let trace = recorder.chrome_trace();
+        assert!(trace.starts_with("{\"traceEvents\":["));
+        assert!(trace.contains("\"name\":\"slow\""));
+
+        let html = recorder.gantt_html();
+        assert!(html.contains("<svg"));
+        assert!(html.contains(">pipeline</text>"));
+    }
+
+    #[test]
+    fn critical_path_follows_the_slowest_chain() {
+        let ms = Duration::from_millis;
+        let node = |id, label, parent, start, finish| NodeRecord {
+            id,
+            label,
+            parent,
+            start: Some(ms(start)),
+            finish: Some(ms(finish)),
+            cancelled: false,
+            polls: Vec::new(),
+        };
+
+        // join(sequence(discount code, discount), products)
+        let nodes = [
+            node(0, "join", None, 0, 90),
+            node(1, "sequence", Some(0), 0, 90),
+            node(2, "discount code", Some(1), 0, 30),
+            node(3, "discount", Some(1), 30, 90),
+            node(4, "products", Some(0), 0, 50),
+        ];
+
+        let report = critical_path(&nodes);
+        let slack = |id: usize| {
+            report
+                .nodes
+                .iter()
+                .find(|node| node.id == id)
+                .unwrap()
+                .slack
+        };
+
+        assert_eq!(report.total, ms(90));
+        assert_eq!(report.path, vec![0, 1, 2, 3]);
+        assert_eq!(slack(2), Duration::ZERO);
+        assert_eq!(slack(4), ms(40));
+
+        // Ids don't have to match the positions of the records
+        let shifted: Vec<NodeRecord> = nodes
+            .iter()
+            .rev()
+            .map(|node| NodeRecord {
+                id: node.id + 10,
+                parent: node.parent.map(|parent| parent + 10),
+                ..node.clone()
+            })
+            .collect();
+        assert_eq!(critical_path(&shifted).path, vec![10, 11, 12, 13]);
+    }
+
+    #[test]
+    fn critical_path_leaves_out_cancelled_nodes() {
+        let us = Duration::from_micros;
+        let node = |id, label, parent, finish, cancelled| NodeRecord {
+            id,
+            label,
+            parent,
+            start: Some(Duration::ZERO),
+            finish: Some(us(finish)),
+            cancelled,
+            polls: Vec::new(),
+        };
+
+        // select(winner, loser), the loser being dropped just after the select completed
+        let nodes = [
+            node(0, "select", None, 16_641, false),
+            node(1, "winner", Some(0), 16_616, false),
+            node(2, "loser", Some(0), 16_648, true),
+            node(3, "loser lookup", Some(2), 2_000, false),
+        ];
+
+        let report = critical_path(&nodes);
+        assert_eq!(report.total, us(16_641));
+        assert_eq!(report.path, vec![0, 1]);
+        assert_eq!(report.cancelled, vec![2, 3]);
+        assert_eq!(report.nodes.len(), 2);
+        assert_eq!(report.nodes[1].slack, Duration::ZERO);
+    }
+}
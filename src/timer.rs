@@ -0,0 +1,180 @@
+//! Timer abstraction used by the time-based combinators, so they can run on tokio's timer
+//! in production and on a manually driven clock in tests.
+
+use core::{future::Future, time::Duration};
+
+#[cfg(feature = "std")]
+use std::{
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+pub trait Timer {
+    type Sleep: Future<Output = ()>;
+
+    fn sleep(&self, duration: Duration) -> Self::Sleep;
+}
+
+#[cfg(feature = "tokio")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioTimer;
+
+#[cfg(feature = "tokio")]
+impl Timer for TokioTimer {
+    type Sleep = tokio::time::Sleep;
+
+    fn sleep(&self, duration: Duration) -> Self::Sleep {
+        tokio::time::sleep(duration)
+    }
+}
+
+#[cfg(feature = "std")]
+struct ManualClock {
+    now: Duration,
+    next_id: u64,
+    // One entry per pending sleep, keyed by its id
+    sleepers: Vec<(u64, Duration, Waker)>,
+}
+
+#[cfg(feature = "std")]
+/// A timer whose clock only moves when [`ManualTimer::advance`] is called.
+/// Clones share the same clock.
+#[derive(Clone)]
+pub struct ManualTimer {
+    clock: Arc<Mutex<ManualClock>>,
+}
+
+#[cfg(feature = "std")]
+impl ManualTimer {
+    pub fn new() -> Self {
+        ManualTimer {
+            clock: Arc::new(Mutex::new(ManualClock {
+                now: Duration::ZERO,
+                next_id: 0,
+                sleepers: Vec::new(),
+            })),
+        }
+    }
+
+    /// Time elapsed since the timer was created.
+    pub fn now(&self) -> Duration {
+        self.clock.lock().unwrap().now
+    }
+
+    /// Moves the clock forward, waking every sleep whose deadline has passed.
+    pub fn advance(&self, duration: Duration) {
+        let expired = {
+            let mut clock = self.clock.lock().unwrap();
+            clock.now += duration;
+            let now = clock.now;
+
+            let (expired, pending) = std::mem::take(&mut clock.sleepers)
+                .into_iter()
+                .partition(|(_, deadline, _)| *deadline <= now);
+            clock.sleepers = pending;
+            expired
+        };
+
+        for (_, _, waker) in expired {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for ManualTimer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl core::fmt::Debug for ManualTimer {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let clock = self.clock.lock().unwrap();
+        f.debug_struct("ManualTimer")
+            .field("now", &clock.now)
+            .field("sleepers", &clock.sleepers.len())
+            .finish()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Timer for ManualTimer {
+    type Sleep = ManualSleep;
+
+    fn sleep(&self, duration: Duration) -> Self::Sleep {
+        let mut clock = self.clock.lock().unwrap();
+        let id = clock.next_id;
+        clock.next_id += 1;
+
+        ManualSleep {
+            id,
+            deadline: clock.now + duration,
+            clock: self.clock.clone(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+pub struct ManualSleep {
+    id: u64,
+    deadline: Duration,
+    clock: Arc<Mutex<ManualClock>>,
+}
+
+#[cfg(feature = "std")]
+impl Future for ManualSleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut clock = self.clock.lock().unwrap();
+        if clock.now >= self.deadline {
+            return Poll::Ready(());
+        }
+
+        let id = self.id;
+        match clock.sleepers.iter_mut().find(|(other, _, _)| *other == id) {
+            Some((_, _, existing)) if existing.will_wake(cx.waker()) => {}
+            Some((_, _, existing)) => *existing = cx.waker().clone(),
+            None => clock.sleepers.push((id, self.deadline, cx.waker().clone())),
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(feature = "std")]
+impl Drop for ManualSleep {
+    fn drop(&mut self) {
+        let id = self.id;
+        self.clock.lock().unwrap().sleepers.retain(|(other, _, _)| *other != id);
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::task::{Context, Poll, Waker};
+
+    use crate::timer::*;
+
+    #[test]
+    fn manual_sleep_registers_once_and_unregisters_on_drop() {
+        let timer = ManualTimer::new();
+        let mut cx = Context::from_waker(Waker::noop());
+
+        let mut sleep = Box::pin(timer.sleep(Duration::from_millis(10)));
+        for _ in 0..3 {
+            assert!(sleep.as_mut().poll(&mut cx).is_pending());
+        }
+        assert_eq!(timer.clock.lock().unwrap().sleepers.len(), 1);
+
+        drop(sleep);
+        assert!(timer.clock.lock().unwrap().sleepers.is_empty());
+
+        let mut sleep = Box::pin(timer.sleep(Duration::from_millis(10)));
+        assert!(sleep.as_mut().poll(&mut cx).is_pending());
+        timer.advance(Duration::from_millis(10));
+        assert_eq!(sleep.as_mut().poll(&mut cx), Poll::Ready(()));
+    }
+}